@@ -1,12 +1,15 @@
 extern crate num_cpus;
 
+use clap::ValueEnum;
 use console::{style, Emoji};
 use glob::glob;
-use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressStyle};
 use ini::Ini;
 use std::env;
 use std::fs::*;
+use std::io::Write;
 use std::os::unix::fs;
+use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::path::*;
 use std::process;
@@ -18,11 +21,17 @@ use tar::Archive;
 use tempdir::TempDir;
 
 use crate::config;
+use crate::elixir;
+use crate::rebar3;
+use crate::repo;
+use crate::verify;
+use crate::version;
+use crate::version::OtpVersion;
 
 // http://unicode.org/emoji/charts/full-emoji-list.html
-static CHECKMARK: Emoji = Emoji("✅", "✅ ");
-static FAIL: Emoji = Emoji("❌", "❌ ");
-static WARNING: Emoji = Emoji("🚫", "🚫");
+pub static CHECKMARK: Emoji = Emoji("✅", "✅ ");
+pub static FAIL: Emoji = Emoji("❌", "❌ ");
+pub static WARNING: Emoji = Emoji("🚫", "🚫");
 
 pub const BINS: [&str; 11] = [
     "bin/ct_run",
@@ -38,8 +47,29 @@ pub const BINS: [&str; 11] = [
     "bin/typer",
 ];
 
+/// Extra emulator types that can be built and installed next to the regular
+/// one, started with `erl -emu_type <flavor>`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Flavor {
+    Debug,
+    Asan,
+    Valgrind,
+    Lcnt,
+}
+
+impl Flavor {
+    pub fn emu_type(&self) -> &'static str {
+        match self {
+            Flavor::Debug => "debug",
+            Flavor::Asan => "asan",
+            Flavor::Valgrind => "valgrind",
+            Flavor::Lcnt => "lcnt",
+        }
+    }
+}
+
 #[derive(Copy, Clone)]
-enum BuildResult {
+pub enum BuildResult {
     Success,
     Fail,
 }
@@ -50,61 +80,92 @@ struct CheckContext<'a> {
     build_status: BuildResult,
 }
 
-enum CheckResult<'a> {
+enum CheckResult {
     Success,
-    Warning(&'a str),
-    Fail,
+    Warning(String),
+    Fail(String),
 }
 
 enum BuildStep<'a> {
     Exec(&'a str, Vec<String>),
-    Check(Box<dyn Fn(&CheckContext) -> CheckResult<'a>>),
+    Check(Box<dyn Fn(&CheckContext) -> CheckResult + 'a>),
 }
 
-pub fn latest_tag(repo_dir: PathBuf) -> String {
-    let output = Command::new("git")
-        .args(["rev-list", "--tags", "--max-count=1"])
-        .current_dir(repo_dir.as_path())
-        .output()
-        .unwrap_or_else(|e| {
-            error!("git rev-list failed: {}", e);
-            process::exit(1)
-        });
+const KEPT_MARKER: &str = ".erlup_kept";
+const BUILDING_MARKER: &str = ".erlup_building";
 
-    if !output.status.success() {
+// a fresh tree extracted for a single build, or a git worktree that is kept
+// around so `--incremental` builds only recompile what changed
+pub enum BuildDir {
+    Temp(TempDir),
+    Worktree(PathBuf),
+}
+
+impl BuildDir {
+    pub fn path(&self) -> &Path {
+        match self {
+            BuildDir::Temp(dir) => dir.path(),
+            BuildDir::Worktree(dir) => dir,
+        }
+    }
+
+    // stops a temporary tree from being deleted, returning where it is. The
+    // marker tells `erlup clean` it's a kept tree and not a build in progress.
+    fn keep(self) -> PathBuf {
+        match self {
+            BuildDir::Temp(dir) => {
+                let dir = dir.into_path();
+                let _ = write(dir.join(KEPT_MARKER), "");
+                dir
+            }
+            BuildDir::Worktree(dir) => dir,
+        }
+    }
+}
+
+pub fn latest_tag(repo_dir: PathBuf, spec: &version::LatestSpec) -> String {
+    if !repo_dir.is_dir() {
         error!(
-            "finding latest tag of {:?} failed: {}",
-            repo_dir,
-            String::from_utf8_lossy(&output.stderr)
+            "Repo not found at {:?}, fetch it first with `erlup fetch`",
+            repo_dir
         );
         process::exit(1);
     }
 
-    let rev = str::from_utf8(&output.stdout).unwrap();
-    let output = Command::new("git")
-        .args(["describe", "--tags", (rev.trim())])
-        .current_dir(repo_dir.clone())
-        .output()
-        .unwrap_or_else(|e| {
-            error!("git describe failed: {}", e);
+    let versions = version::sorted_versions(&git_tags(&repo_dir));
+    match spec.select(&versions) {
+        Some(v) => v.to_string(),
+        None => {
+            error!("No tag in {:?} matches {}", repo_dir, spec);
+            error!("Fetch the latest tags with `erlup fetch`");
             process::exit(1)
-        });
+        }
+    }
+}
 
-    if !output.status.success() {
-        error!(
-            "describing latest tag of {:?} failed: {}",
-            repo_dir,
-            String::from_utf8_lossy(&output.stderr)
-        );
-        process::exit(1);
+/// The executables erlup links to itself, Elixir's and rebar3 only once there's
+/// an install with them so ones installed some other way aren't shadowed.
+pub fn linked_bins(config: &Ini) -> Vec<&'static str> {
+    let mut bins = BINS.to_vec();
+    if !config::get_elixirs(config).is_empty() {
+        bins.extend(elixir::BINS);
+    }
+    if config::get_erlangs(config)
+        .iter()
+        .any(|(_, dir)| Path::new(dir).join(rebar3::BIN).exists())
+    {
+        bins.push(rebar3::BIN);
     }
+    bins
+}
 
-    String::from_utf8_lossy(&output.stdout).trim().to_string()
+pub fn update_bins(bin_path: &Path, links_dir: &Path, config: &Ini) {
+    link_bins(&linked_bins(config), bin_path, links_dir);
 }
 
-pub fn update_bins(bin_path: &Path, links_dir: &Path) {
+pub fn link_bins(bins: &[&str], bin_path: &Path, links_dir: &Path) {
     let _ = std::fs::create_dir_all(links_dir);
-    for &b in BINS.iter() {
+    for &b in bins.iter() {
         let f = Path::new(b).file_name().unwrap();
         let link = links_dir.join(f);
         debug!("linking {} to {}", link.display(), bin_path.display());
@@ -113,7 +174,7 @@ pub fn update_bins(bin_path: &Path, links_dir: &Path) {
     }
 }
 
-pub fn tags(repo: String, config: Ini) {
+pub fn tags(repo: String, major: Option<u32>, include_rc: bool, limit: Option<usize>, config: Ini) {
     let git_repo = &config::lookup("repos", repo.to_string(), &config).unwrap();
     let dir = &config::lookup_cache_dir(&config);
     let repo_dir = Path::new(dir).join("repos").join(repo);
@@ -124,9 +185,21 @@ pub fn tags(repo: String, config: Ini) {
             git_repo,
             repo_dir.to_str().unwrap()
         );
-        clone_repo(git_repo, repo_dir.to_owned());
+        clone_repo(git_repo, repo_dir.to_owned(), &config);
+    }
+
+    let versions = version::sorted_versions(&git_tags(&repo_dir))
+        .into_iter()
+        .filter(|v| include_rc || !v.is_rc())
+        .filter(|v| major.is_none_or(|major| v.major() == major))
+        .take(limit.unwrap_or(usize::MAX));
+
+    for v in versions {
+        println!("{}", v);
     }
+}
 
+fn git_tags(repo_dir: &Path) -> Vec<String> {
     let output = Command::new("git")
         .args(["tag"])
         .current_dir(repo_dir)
@@ -141,7 +214,11 @@ pub fn tags(repo: String, config: Ini) {
         process::exit(1);
     }
 
-    println!("{}", String::from_utf8_lossy(&output.stdout).trim());
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect()
 }
 
 pub fn branches(repo: String, config: Ini) {
@@ -155,11 +232,11 @@ pub fn branches(repo: String, config: Ini) {
             git_repo,
             repo_dir.to_str().unwrap()
         );
-        clone_repo(git_repo, repo_dir.to_owned());
+        clone_repo(git_repo, repo_dir.to_owned(), &config);
     }
 
     let output = Command::new("git")
-        .args(["branch"])
+        .args(["branch", "--all", "--format=%(refname)"])
         .current_dir(repo_dir)
         .output()
         .unwrap_or_else(|e| {
@@ -168,73 +245,137 @@ pub fn branches(repo: String, config: Ini) {
         });
 
     if !output.status.success() {
-        error!("tag failed: {}", String::from_utf8_lossy(&output.stderr));
+        error!("branch failed: {}", String::from_utf8_lossy(&output.stderr));
         process::exit(1);
     }
 
-    println!("{}", String::from_utf8_lossy(&output.stdout).trim());
+    // group by remote, printing the names as `build` takes them, eg. origin/maint
+    let mut groups: Vec<(String, Vec<String>)> = vec![];
+    for refname in String::from_utf8_lossy(&output.stdout).lines() {
+        let (group, branch) = if let Some(branch) = refname.strip_prefix("refs/heads/") {
+            ("local".to_string(), branch.to_string())
+        } else if let Some(branch) = refname.strip_prefix("refs/remotes/") {
+            match branch.split_once('/') {
+                Some((_, "HEAD")) | None => continue,
+                Some((remote, _)) => (remote.to_string(), branch.to_string()),
+            }
+        } else {
+            continue;
+        };
+
+        match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, branches)) => branches.push(branch),
+            None => groups.push((group, vec![branch])),
+        }
+    }
+
+    for (group, branches) in groups {
+        println!("{}:", group);
+        for branch in branches {
+            println!("  {}", branch);
+        }
+    }
 }
 
 pub fn fetch(maybe_repo: Option<String>, config: Ini) {
     let repo = maybe_repo.unwrap_or("default".to_string());
-    let git_repo = &config::lookup("repos", repo.clone(), &config).unwrap_or_else(|| {
-        error!("Repo {} not found in config", repo);
-        process::exit(1)
-    });
-    let dir = &config::lookup_cache_dir(&config);
-    let repo_dir = Path::new(dir).join("repos").join(repo);
+    fetch_repos(vec![repo], &config);
+}
 
-    let started = Instant::now();
-    let spinner_style = ProgressStyle::default_spinner()
-        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
-        .template("{prefix:.bold.dim} {spinner} {wide_msg}")
-        .unwrap();
+pub fn fetch_all(config: Ini) {
+    let repos = config::get_repos(&config)
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect();
+    fetch_repos(repos, &config);
+}
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(spinner_style);
-    pb.enable_steady_tick(Duration::from_millis(100));
+fn fetch_repos(repos: Vec<String>, config: &Ini) {
+    let dir = &config::lookup_cache_dir(config);
 
-    if !repo_dir.exists() {
-        pb.set_message(format!(
-            "Cloning repo {} to {}",
-            git_repo,
-            repo_dir.to_str().unwrap()
-        ));
-        clone_repo(git_repo, repo_dir.to_owned());
-        pb.println(format!(
-            " {} Cloning repo {} to {:?}",
-            CHECKMARK, git_repo, repo_dir
-        ));
-    }
+    let started = Instant::now();
+    let pb = spinner();
 
-    pb.set_message(format!("Fetching tags from {}", git_repo));
-    let output = Command::new("git")
-        .args(["fetch"])
-        .current_dir(repo_dir)
-        .output()
-        .unwrap_or_else(|e| {
-            error!("git fetch failed: {} {}", dir, e);
+    for repo in repos {
+        let git_repo = &config::lookup("repos", repo.clone(), config).unwrap_or_else(|| {
+            error!("Repo {} not found in config", repo);
             process::exit(1)
         });
+        let repo_dir = Path::new(dir).join("repos").join(&repo);
 
-    if !output.status.success() {
-        error!("fetch failed: {}", String::from_utf8_lossy(&output.stderr));
-        process::exit(1);
+        if !repo_dir.exists() {
+            pb.set_message(format!(
+                "Cloning repo {} to {}",
+                git_repo,
+                repo_dir.to_str().unwrap()
+            ));
+            clone_repo(git_repo, repo_dir.to_owned(), config);
+            pb.println(format!(
+                " {} Cloning repo {} to {:?}",
+                CHECKMARK, git_repo, repo_dir
+            ));
+        }
+
+        if repo::uses_store(&repo_dir) {
+            pb.set_message(format!(
+                "Fetching {} into the shared object store",
+                git_repo
+            ));
+            repo::fetch_into_store(&repo, git_repo, config);
+        }
+
+        // a plain fetch only gets tags pointing into fetched branches and never
+        // removes branches deleted upstream. Pruning is done without --tags, which
+        // would make it delete every tag one remote doesn't have, even when it
+        // came from another remote, so the tags are fetched separately.
+        pb.set_message(format!("Fetching tags from {}", git_repo));
+        for args in [["fetch", "--all", "--prune"], ["fetch", "--all", "--tags"]] {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&repo_dir)
+                .output()
+                .unwrap_or_else(|e| {
+                    error!("git fetch failed: {} {}", dir, e);
+                    process::exit(1)
+                });
+
+            if !output.status.success() {
+                pb.println(format!(" {} Fetching tags from {}", FAIL, git_repo));
+                error!("fetch failed: {}", String::from_utf8_lossy(&output.stderr));
+                process::exit(1);
+            }
+        }
+
+        pb.println(format!(" {} Fetching tags from {}", CHECKMARK, git_repo));
     }
 
-    pb.println(format!(" {} Fetching tags from {}", CHECKMARK, git_repo));
     pb.finish_and_clear();
-    println!(
+    eprintln!(
         "{} fetch in {}",
         style("Finished").green().bold(),
         HumanDuration(started.elapsed())
     );
 }
 
-fn clone_repo(git_repo: &str, repo_dir: std::path::PathBuf) {
+pub fn clone_repo(git_repo: &str, repo_dir: std::path::PathBuf, config: &Ini) {
+    let name = repo_dir.file_name().unwrap().to_str().unwrap().to_string();
+    let mut args = vec!["clone".to_string()];
+    if repo::partial_clone(&name, config) {
+        // blobs are only downloaded once a checkout needs them
+        args.push("--filter=blob:none".to_string());
+    } else if repo::shared_objects(config) {
+        // objects common to all repos, most of them for forks of otp, are only
+        // stored once in the shared store and borrowed through alternates
+        let store = repo::fetch_into_store(&name, git_repo, config);
+        args.push("--reference".to_string());
+        args.push(store.to_str().unwrap().to_string());
+    }
+    args.push(git_repo.to_string());
+    args.push(".".to_string());
+
     let _ = std::fs::create_dir_all(&repo_dir);
     let output = Command::new("git")
-        .args(["clone", git_repo, "."])
+        .args(&args)
         .current_dir(&repo_dir)
         .output()
         .unwrap_or_else(|e| {
@@ -252,49 +393,102 @@ fn clone_repo(git_repo: &str, repo_dir: std::path::PathBuf) {
 pub fn run(
     bin_path: PathBuf,
     git_ref: String,
-    id: String,
+    id: Option<String>,
     repo: String,
     repo_url: String,
     force: bool,
+    incremental: bool,
+    keep_build_dir: bool,
+    extra_configure_options: &[String],
+    flavors: &[Flavor],
     config_file: &str,
     config: Ini,
-) {
+) -> bool {
     let dir = &config::lookup_cache_dir(&config);
 
-    let key = "ERLUP_CONFIGURE_OPTIONS";
-    let empty_string = &"".to_string();
-    let user_configure_options = match env::var(key) {
-        Ok(options) => options,
-        _ => {
-            config::lookup_with_default("erlup", "default_configure_options", empty_string, &config)
-                .to_owned()
-        }
-    };
+    let user_configure_options = configure_options(&repo, extra_configure_options, &config);
+    // checked before a build tree is made, exiting later would leave it behind
+    if let Err(e) = shell_words::split(&user_configure_options) {
+        error!("bad configure options {}\n\t{}", user_configure_options, e);
+        process::exit(1);
+    }
+    let required_apps = config::required_apps(&repo, &config);
     let links_dir = Path::new(dir).join("bin");
-    let repo_dir = Path::new(dir).join("repos").join(repo);
+    let repo_dir = Path::new(dir).join("repos").join(&repo);
+
+    if !repo_dir.is_dir() {
+        info!("Cloning repo {} to {}", repo_url, repo_dir.display());
+        clone_repo(&repo_url, repo_dir.to_owned(), &config);
+    }
+
+    let resolved = resolve_ref(&repo_dir, &git_ref);
+    info!("Resolved {} to commit {}", git_ref, resolved.commit);
+    let id = id.unwrap_or_else(|| default_id(&git_ref, &resolved, incremental));
 
     let install_dir = Path::new(dir).join("otps").join(id.clone());
 
-    if !install_dir.exists() || force {
+    // an incremental build replaces the install its worktree built before,
+    // any other install of the same id still needs --force
+    let rebuilding = incremental && worktree_dir(&id, &config).exists();
+    if !install_dir.exists() || force || rebuilding {
         debug!("building {}:", id);
         debug!("    repo url: {}", repo_url);
         debug!("    repo dir: {:?}", repo_dir);
         debug!("    install: {:?}", install_dir);
         debug!("    git_ref: {}", git_ref);
+        debug!("    commit: {}", resolved.commit);
         debug!("    options: {}", user_configure_options);
+        debug!("    flavors: {:?}", flavors);
+        debug!("    required apps: {:?}", required_apps);
         debug!("    force: {}", force);
-        build(
-            repo_url,
+        let build_dir = if incremental {
+            BuildDir::Worktree(worktree_dir(&id, &config))
+        } else {
+            temp_build_dir(&id, &config)
+        };
+        debug!("    build dir: {:?}", build_dir.path());
+        let building = BuildingMarker::create(&id, &config);
+        let healthy = build(
             repo_dir,
             install_dir.as_path(),
-            git_ref,
+            git_ref.clone(),
+            &resolved.commit,
             &user_configure_options,
+            flavors,
+            &required_apps,
+            build_dir,
+            keep_build_dir,
         );
-        update_bins(bin_path.as_path(), links_dir.as_path());
+        update_bins(bin_path.as_path(), links_dir.as_path(), &config);
 
-        // update config file with new built otp entry
+        // update config file with new built otp entry, marking it broken if it
+        // didn't pass the smoke test so it isn't mistaken for a working install
         let dist = install_dir.join("dist");
-        config::update(id, dist.to_str().unwrap(), config_file);
+        config::update(id.clone(), dist.to_str().unwrap(), config_file);
+        let status = if healthy { "ok" } else { "broken" };
+        config::update_install_option(&id, "status", status, config_file);
+        config::update_install_option(&id, "repo", &repo, config_file);
+        config::update_install_option(&id, "ref", &git_ref, config_file);
+        config::update_install_option(&id, "commit", &resolved.commit, config_file);
+        // the build's own options, so `erlup upgrade` can build the same way
+        if extra_configure_options.is_empty() {
+            config::delete_install_option(&id, "configure_opts", config_file);
+        } else {
+            let opts = shell_words::join(extra_configure_options);
+            config::update_install_option(&id, "configure_opts", &opts, config_file);
+        }
+        if flavors.is_empty() {
+            config::delete_install_option(&id, "flavors", config_file);
+        } else {
+            let flavors: Vec<&str> = flavors.iter().map(Flavor::emu_type).collect();
+            config::update_install_option(&id, "flavors", &flavors.join(","), config_file);
+        }
+        if let Some(vsn) = version::read_otp_version(&dist) {
+            config::update_install_option(&id, "otp_version", &vsn, config_file);
+        }
+        verify::write_manifest(&dist);
+        drop(building);
+        healthy
     } else {
         error!("Directory for {} already exists: {:?}", id, install_dir);
         error!("If this is incorrect remove that directory,");
@@ -303,301 +497,1432 @@ pub fn run(
     }
 }
 
-pub fn delete(id: String, config_file: &str, config: Ini) {
-    let dir = &config::lookup_cache_dir(&config);
+// build trees are made under the cache dir, rather than the system's temp dir,
+// so the ones kept for inspection can be found again by `erlup clean`
+pub fn builds_dir(config: &Ini) -> PathBuf {
+    let dir = config::lookup_cache_dir(config);
+    Path::new(dir).join("builds")
+}
 
-    let install_dir = Path::new(dir).join("otps").join(id.clone());
-    let install_dir_str = install_dir.to_str().unwrap();
+pub fn temp_build_dir(id: &str, config: &Ini) -> BuildDir {
+    let builds = builds_dir(config);
+    let _ = create_dir_all(&builds);
+    match TempDir::new_in(&builds, id) {
+        Ok(dir) => {
+            // the builder's pid, so `erlup clean` can tell a running build from
+            // one that was killed
+            let _ = write(dir.path().join(BUILDING_MARKER), process::id().to_string());
+            BuildDir::Temp(dir)
+        }
+        Err(e) => {
+            error!("failed creating temp directory for build: {}", e);
+            process::exit(1)
+        }
+    }
+}
 
-    debug!("deleting {} at {}:", id, install_dir_str);
+/// Removes the build trees kept by failed builds and `--keep-build-dir`.
+pub fn clean(config: &Ini) {
+    let mut removed = 0;
+    if let Ok(entries) = read_dir(builds_dir(config)) {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            // the `<id>.building` files of builds that didn't get to remove them
+            if !path.is_dir() {
+                if path.extension().is_some_and(|e| e == "building") && !lock_owner_alive(&path) {
+                    let _ = remove_file(&path);
+                }
+                continue;
+            }
+            // trees without the marker belong to builds still running, unless
+            // their builder is gone
+            if !path.join(KEPT_MARKER).exists() && lock_owner_alive(&path.join(BUILDING_MARKER)) {
+                debug!("skipping {}, its build is still running", path.display());
+                continue;
+            }
+            let size = repo::dir_size(&path);
+            remove_dir_all(&path).unwrap_or_else(|e| {
+                error!("unable to delete {} due to {}", path.display(), e);
+                process::exit(1)
+            });
+            info!("Deleted {} ({})", path.display(), HumanBytes(size));
+            removed += 1;
+        }
+    }
 
-    // remove the entry from config
-    config::delete(id, config_file);
+    if removed == 0 {
+        info!("No build trees to remove");
+    }
+}
 
-    // delete the install dir from disk
-    std::fs::remove_dir_all(install_dir_str).unwrap_or_else(|e| {
-        error!("unable to delete {} due to {}", install_dir_str, e);
-        process::exit(1);
-    });
+// the persistent source tree of an id's `--incremental` builds
+fn worktree_dir(id: &str, config: &Ini) -> PathBuf {
+    let dir = config::lookup_cache_dir(config);
+    Path::new(dir).join("worktrees").join(id).join("otp")
+}
+
+pub struct ResolvedRef {
+    pub commit: String,
+    pub is_tag: bool,
+}
+
+/// The id a build of `git_ref` gets when none is given. Branches move, so their
+/// builds get the commit in the id to not replace older builds, unless it's an
+/// incremental build which is meant to replace them.
+pub fn default_id(git_ref: &str, resolved: &ResolvedRef, incremental: bool) -> String {
+    if resolved.is_tag {
+        git_ref.trim_start_matches("refs/tags/").to_string()
+    } else if incremental && !resolved.commit.starts_with(git_ref) {
+        git_ref.replace('/', "-")
+    } else {
+        let short = &resolved.commit[..resolved.commit.len().min(10)];
+        if resolved.commit.starts_with(git_ref) {
+            short.to_string()
+        } else {
+            format!("{}-{}", git_ref.replace('/', "-"), short)
+        }
+    }
 }
 
-fn run_git(args: Vec<&str>) {
+fn rev_parse(repo_dir: &Path, rev: &str) -> Option<String> {
     let output = Command::new("git")
-        .args(&args)
+        .args(["rev-parse", "--verify", "--quiet", rev])
+        .current_dir(repo_dir)
         .output()
-        .unwrap_or_else(|e| {
-            error!("git command failed: {}", e);
-            process::exit(1)
-        });
+        .ok()?;
 
     if !output.status.success() {
-        error!("clone failed: {}", String::from_utf8_lossy(&output.stderr));
-        process::exit(1);
+        return None;
     }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
 }
 
-fn clone(repo: String, dest: &str) {
-    run_git(vec!["clone", repo.as_str(), dest]);
+/// Resolves a tag, branch, remote branch, commit or any other ref, like
+/// refs/pull/1234/head, to the commit to build, fetching it if it isn't known yet.
+pub fn resolve_ref(repo_dir: &Path, git_ref: &str) -> ResolvedRef {
+    let mut candidates: Vec<(String, String)> = [
+        "refs/tags/",
+        "refs/remotes/origin/",
+        "refs/heads/",
+        "refs/remotes/",
+    ]
+    .iter()
+    .map(|prefix| format!("{}{}", prefix, git_ref))
+    .filter_map(|r| rev_parse(repo_dir, &format!("{}^{{commit}}", r)).map(|c| (r, c)))
+    .collect();
+    // fetch only moves origin's branches, a local branch of the same name is
+    // usually the stale one made by the clone
+    if candidates
+        .iter()
+        .any(|(r, _)| r.starts_with("refs/remotes/origin/"))
+    {
+        candidates.retain(|(r, _)| !r.starts_with("refs/heads/"));
+    }
+    if candidates.iter().any(|(_, c)| *c != candidates[0].1) {
+        error!("{} is ambiguous, it can be any of:", git_ref);
+        for (r, c) in candidates.iter() {
+            error!("    {} ({})", r, c);
+        }
+        error!("Build using one of the full ref names instead.");
+        process::exit(1);
+    }
+    let mut is_tag = git_ref.starts_with("refs/tags/")
+        || candidates.iter().any(|(r, _)| r.starts_with("refs/tags/"));
+
+    let commit = match candidates.first() {
+        Some((_, commit)) => commit.clone(),
+        None => match rev_parse(repo_dir, &format!("{}^{{commit}}", git_ref)) {
+            Some(commit) => commit,
+            None => {
+                let commit = fetch_ref(repo_dir, git_ref);
+                // fetch_ref keeps a fetched tag, so a tag new to the clone
+                // gets the same id as one that was already there
+                let tag = format!("refs/tags/{}^{{commit}}", git_ref);
+                is_tag = is_tag || rev_parse(repo_dir, &tag).as_ref() == Some(&commit);
+                commit
+            }
+        },
+    };
+
+    ResolvedRef { commit, is_tag }
 }
 
-fn checkout(dir: &Path, repo_dir: &Path, vsn: &str, pb: &ProgressBar) {
-    let otp_tar = dir.join("otp.tar");
-    debug!("otp_tar={}", otp_tar.to_str().unwrap());
+fn fetch_ref(repo_dir: &Path, git_ref: &str) -> String {
     let output = Command::new("git")
-        .args(["archive", "-o", otp_tar.to_str().unwrap(), vsn])
+        .args(["remote"])
         .current_dir(repo_dir)
         .output()
         .unwrap_or_else(|e| {
-            error!("git archive failed: {}", e);
+            error!("git remote failed: {}", e);
+            process::exit(1)
+        });
+    let remotes = String::from_utf8_lossy(&output.stdout).to_string();
+
+    // origin/beamasm is the branch beamasm of the remote origin, anything else is
+    // fetched from origin as is
+    let (remote, remote_ref) = match git_ref.split_once('/') {
+        Some((remote, r)) if remotes.lines().any(|l| l == remote) => (remote, r),
+        _ => ("origin", git_ref),
+    };
+
+    info!("{} not found locally, fetching it from {}", git_ref, remote);
+    let output = Command::new("git")
+        .args(["fetch", remote, remote_ref])
+        .current_dir(repo_dir)
+        .output()
+        .unwrap_or_else(|e| {
+            error!("git fetch failed: {}", e);
             process::exit(1)
         });
 
     if !output.status.success() {
-        pb.println(format!(" {} Checking out {}", FAIL, vsn));
         error!(
-            "checkout of {} failed: {}",
-            vsn,
-            String::from_utf8_lossy(&output.stderr)
+            "{} not found in the repo or its remote {}: {}",
+            git_ref,
+            remote,
+            String::from_utf8_lossy(&output.stderr).trim()
         );
         process::exit(1);
     }
 
-    let mut ar = Archive::new(File::open(otp_tar).unwrap());
-    ar.unpack(dir).unwrap();
+    // a tag fetched by name only lands in FETCH_HEAD, keep it as a local tag
+    let fetch_head = read_to_string(repo_dir.join(".git").join("FETCH_HEAD")).unwrap_or_default();
+    let fetched_tag = fetch_head
+        .lines()
+        .next()
+        .and_then(|l| l.split_once("\ttag '"))
+        .and_then(|(_, rest)| rest.split_once('\''))
+        .map(|(tag, _)| tag.to_string());
+    if let Some(tag) = fetched_tag {
+        let _ = Command::new("git")
+            .args(["update-ref", &format!("refs/tags/{}", tag), "FETCH_HEAD"])
+            .current_dir(repo_dir)
+            .output();
+    }
+
+    rev_parse(repo_dir, "FETCH_HEAD^{commit}").unwrap_or_else(|| {
+        error!("{} fetched from {} is not a commit", git_ref, remote);
+        process::exit(1)
+    })
 }
 
-fn setup_links(install_dir: &Path) {
-    for &b in BINS.iter() {
-        let f = Path::new(b).file_name().unwrap();
-        let bin = install_dir.join("dist").join(b);
-        let paths = glob(bin.to_str().unwrap()).unwrap();
+// configure options are built up from, in order: the env variable ERLUP_CONFIGURE_OPTIONS
+// or the global default_configure_options, the repo's own `[repo.<name>]` configure_options
+// and finally any `--configure-opt` given on the command line
+fn configure_options(repo: &str, extra_configure_options: &[String], config: &Ini) -> String {
+    let key = "ERLUP_CONFIGURE_OPTIONS";
+    let empty_string = &"".to_string();
+    let default_options = match env::var(key) {
+        Ok(options) => options,
+        _ => {
+            config::lookup_with_default("erlup", "default_configure_options", empty_string, config)
+                .to_owned()
+        }
+    };
+    let repo_options = config::lookup_repo_option(repo, "configure_options", config);
 
-        match paths.last() {
-            Some(x) => {
-                let link = install_dir.join(f);
-                let _ = fs::symlink(x.unwrap().to_str().unwrap(), link);
+    let mut options = vec![default_options];
+    options.extend(repo_options);
+    if !extra_configure_options.is_empty() {
+        options.push(shell_words::join(extra_configure_options));
+    }
+
+    options
+        .into_iter()
+        .filter(|o| !o.trim().is_empty())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Builds the Erlang an erlup.config asks for but isn't installed, returning its
+/// install directory. A version requirement builds the newest matching tag of the
+/// default repo, anything else is built as a ref with that id.
+pub fn auto_install(erl_to_use: &str, config_file: &str, config: Ini) -> String {
+    // shims started at the same time wait for the first one's build, then use it
+    let lock = Lock::acquire(
+        Path::new(config::lookup_cache_dir(&config)).join("auto_install.lock"),
+        Some("Waiting for another erlup to finish installing Erlang"),
+    );
+    let config = config::read_config(config_file.to_string());
+    if let Some(erl) = config::lookup_erl(erl_to_use, &config) {
+        let built = config::get_erlangs(&config)
+            .into_iter()
+            .find(|(_, dir)| *dir == erl)
+            .map(|(id, _)| id.to_string());
+        if let Some(id) = built {
+            if config::lookup_install_option(&id, "status", &config).as_deref() == Some("broken") {
+                error!("Erlang {} was installed but is broken, not running it", id);
+                error!("See `erlup verify {}` for the problems", id);
+                drop(lock);
+                process::exit(1);
             }
-            None => debug!("file to link not found: {}", f.to_str().unwrap()),
         }
+        return erl;
     }
-}
 
-pub fn build(
-    repo_url: String,
-    repo_dir: PathBuf,
-    install_dir: &Path,
-    vsn: String,
-    user_configure_options0: &str,
-) {
-    if !repo_dir.is_dir() {
-        clone(repo_url, repo_dir.as_os_str().to_str().unwrap());
+    let repo = "default".to_string();
+    let repo_url = config::lookup("repos", repo.clone(), &config).unwrap_or_else(|| {
+        error!("Repo {} not found in config", repo);
+        process::exit(1)
+    });
+    let dir = config::lookup_cache_dir(&config);
+    let repo_dir = Path::new(dir).join("repos").join(&repo);
+
+    if !repo_dir.exists() {
+        fetch(Some(repo.clone()), config.clone());
     }
 
-    let started = Instant::now();
-    let spinner_style = ProgressStyle::default_spinner()
-        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
-        .template("{prefix:.bold.dim} {spinner} {wide_msg}")
-        .unwrap();
+    let (git_ref, id) = match version::VersionReq::parse(erl_to_use) {
+        Some(req) => {
+            let tag = match tag_matching(&repo_dir, &req) {
+                Some(tag) => tag,
+                None => {
+                    // the tag may just be newer than the last fetch
+                    fetch(Some(repo.clone()), config.clone());
+                    tag_matching(&repo_dir, &req).unwrap_or_else(|| {
+                        error!("No tag in repo {} matches version {}", repo, erl_to_use);
+                        process::exit(1)
+                    })
+                }
+            };
+            (tag.clone(), tag)
+        }
+        None => (erl_to_use.to_string(), erl_to_use.to_string()),
+    };
 
-    let pb = ProgressBar::new_spinner();
-    pb.set_style(spinner_style);
-    pb.enable_steady_tick(Duration::from_millis(100));
+    let bin_path = env::current_exe().unwrap_or_else(|e| {
+        error!("failed to get current bin path: {}", e);
+        process::exit(1)
+    });
+    let healthy = run(
+        bin_path,
+        git_ref,
+        Some(id.clone()),
+        repo,
+        repo_url,
+        false,
+        false,
+        false,
+        &[],
+        &[],
+        config_file,
+        config,
+    );
+    if !healthy {
+        error!("Erlang {} was installed but is broken, not running it", id);
+        error!("See `erlup verify {}` for the problems", id);
+        drop(lock);
+        process::exit(1);
+    }
 
-    match TempDir::new("erlup") {
-        Ok(dir) => {
-            let num_cpus = num_cpus::get().to_string();
+    drop(lock);
+    let config = config::read_config(config_file.to_string());
+    config::lookup("erlangs", id.clone(), &config).unwrap_or_else(|| {
+        error!("Erlang {} was not installed", id);
+        process::exit(1)
+    })
+}
 
-            pb.set_message(format!("Checking out {}", vsn));
+// written while an install is built, before it has a config entry, so `erlup gc`
+// doesn't take its directory for an orphan. It has the builder's pid, like the
+// auto install lock, as a build that exits early leaves it behind.
+struct BuildingMarker(PathBuf);
+
+impl BuildingMarker {
+    fn create(id: &str, config: &Ini) -> BuildingMarker {
+        let file = building_file(id, config);
+        let _ = create_dir_all(builds_dir(config));
+        let _ = write(&file, process::id().to_string());
+        BuildingMarker(file)
+    }
+}
 
-            checkout(dir.path(), &repo_dir, &vsn, &pb);
-            let _ = std::fs::create_dir_all(repo_dir);
-            let _ = std::fs::create_dir_all(install_dir);
+impl Drop for BuildingMarker {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.0);
+    }
+}
 
-            pb.println(format!(
-                " {} Checking out {} (done in {})",
-                CHECKMARK,
-                vsn,
-                HumanDuration(started.elapsed())
-            ));
-            debug!("temp dir: {:?}", dir.path());
-
-            let dist_dir = install_dir.join("dist");
-
-            // split the configure options into a vector of String in a shell sensitive way
-            // eg.
-            //  from:
-            //      user_configure_options0: --without-wx --without-observer --without-odbc --without-debugger --without-et --enable-builtin-zlib --without-javac CFLAGS="-g -O2 -march=native"
-            //  to:
-            //      user_configure_options: ["--without-wx", "--without-observer", "--without-odbc", "--without-debugger", "--without-et", "--enable-builtin-zlib", "--without-javac", "CFLAGS=-g -O2 -march=native"]
-            let mut user_configure_options: Vec<String> =
-                shell_words::split(user_configure_options0).unwrap_or_else(|e| {
-                    error!("bad configure options {}\n\t{}", user_configure_options0, e);
-                    process::exit(1);
-                });
-            // basic configure options must always include a prefix
-            let mut configure_options = vec![
-                "--prefix".to_string(),
-                dist_dir.to_str().unwrap().to_string(),
-            ];
-            // append the user defined options
-            configure_options.append(&mut user_configure_options);
-
-            // declare the build pipeline steps
-            let build_steps: [BuildStep; 8] = [
-                BuildStep::Exec("./otp_build", vec!["autoconf".to_string()]),
-                BuildStep::Exec("./configure", configure_options),
-                BuildStep::Check(Box::new(|context| {
-                    if has_openssl(context.src_dir) {
-                        CheckResult::Success
-                    } else {
-                        CheckResult::Warning("No usable OpenSSL found, please specify one with --with-ssl configure option, `crypto` application will not work in current build")
-                    }
-                })),
-                BuildStep::Exec("make", vec!["-j".to_string(), num_cpus.to_string()]),
-                BuildStep::Exec(
-                    "make",
-                    vec![
-                        "-j".to_string(),
-                        num_cpus.to_string(),
-                        "docs".to_string(),
-                        "DOC_TARGETS=chunks".to_string(),
-                    ],
-                ),
-                // after `make` we'll already know if this build failed or not, this allows us
-                // to make a better decision in wether to delete the installation dir should there
-                // be one.
-                BuildStep::Check(Box::new(|context| {
-                    match context.build_status {
-                        BuildResult::Fail => {
-                            debug!("build has failed, aborting install to prevent overwriting a possibly working installation dir");
-                            // this build has failed, we won't touch the previously existing install
-                            // dir, for all we know it could hold a previously working installation
-                            CheckResult::Fail
-                        }
-                        // if the build succeeded, then we check for an already existing
-                        // install dir, if we find one we can delete it and proceed to the
-                        // install phase
-                        BuildResult::Success => {
-                            // is install dir empty? courtesy of StackOverflow
-                            let is_empty = context
-                                .install_dir
-                                .read_dir()
-                                .map(|mut i| i.next().is_none())
-                                .unwrap_or(false);
-                            if is_empty {
-                                // it's fine, it was probably us who created the dir just a moment ago,
-                                // that's why it's empty
-                                CheckResult::Success
-                            } else {
-                                debug!("found a non empty installation dir after a successful build, removing it");
-                                // dir is not empty, maybe a working installation is already there,
-                                // delete the whole thing and proceed, we can go ahead with this
-                                // because we know we have a working build in our hands
-                                let _ = std::fs::remove_dir_all(context.install_dir);
-                                CheckResult::Success
-                            }
-                        }
-                    }
-                })),
-                BuildStep::Exec(
-                    "make",
-                    vec![
-                        "-j".to_string(),
-                        num_cpus.to_string(),
-                        "install".to_string(),
-                    ],
-                ),
-                BuildStep::Exec(
-                    "make",
-                    vec![
-                        "-j".to_string(),
-                        num_cpus.to_string(),
-                        "install-docs".to_string(),
-                    ],
-                ),
-            ];
-            // execute them sequentially
-            let mut build_status = BuildResult::Success;
-            for step in build_steps.iter() {
-                let step_started = Instant::now();
-
-                match step {
-                    BuildStep::Exec(command, args) => {
-                        // it only takes one exec command to fail for the build status
-                        // to be fail as well, a subsequent check build step can optionally decide
-                        // to fail the pipeline
-                        if let BuildResult::Fail =
-                            exec(command, args, dir.path(), step_started, &pb)
-                        {
-                            build_status = BuildResult::Fail;
-                        }
+fn building_file(id: &str, config: &Ini) -> PathBuf {
+    builds_dir(config).join(format!("{}.building", id))
+}
+
+/// Whether a running erlup is building the install `id`.
+pub fn build_in_progress(id: &str, config: &Ini) -> bool {
+    let file = building_file(id, config);
+    file.exists() && lock_owner_alive(&file)
+}
+
+/// A lock file, held while a shim builds a missing Erlang or writes to the
+/// config. It records the owner's pid so a lock left behind by an erlup that
+/// failed, exiting without dropping the lock, or was killed can be taken over.
+pub struct Lock(PathBuf);
+
+impl Lock {
+    /// Waits for the lock, printing `waiting` once if it has to.
+    pub fn acquire(file: PathBuf, waiting: Option<&str>) -> Lock {
+        let mut waited = false;
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&file) {
+                Ok(mut f) => {
+                    let _ = write!(f, "{}", process::id());
+                    return Lock(file);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !lock_owner_alive(&file) {
+                        debug!("removing stale lock {}", file.display());
+                        let _ = remove_file(&file);
+                        continue;
                     }
-                    BuildStep::Check(fun) => {
-                        let context = CheckContext {
-                            src_dir: dir.path(),
-                            install_dir,
-                            build_status,
-                        };
-                        match fun(&context) {
-                            CheckResult::Success => {
-                                debug!("success");
-                            }
-                            CheckResult::Warning(warning) => {
-                                debug!("{}", warning);
-                                pb.set_message(warning);
-                                pb.println(format!(" {} {}", WARNING, warning));
-                            }
-                            CheckResult::Fail => {
-                                // abort
-                                pb.finish_and_clear();
-                                std::process::exit(1);
-                            }
-                        }
+                    if let (Some(waiting), false) = (waiting, waited) {
+                        info!("{}", waiting);
+                        waited = true;
                     }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(e) => {
+                    error!("unable to create lock {}: {}", file.display(), e);
+                    process::exit(1)
                 }
             }
-            // By closing the `TempDir` explicitly, we can check that it has
-            // been deleted successfully. If we don't close it explicitly,
-            // the directory will still be deleted when `tmp_dir` goes out
-            // of scope, but we won't know whether deleting the directory
-            // succeeded.
-            drop(dir);
         }
-        Err(e) => {
-            error!("failed creating temp directory for build: {}", e);
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.0);
+    }
+}
+
+fn lock_owner_alive(file: &Path) -> bool {
+    match read_to_string(file).map(|pid| pid.trim().to_string()) {
+        // the lock may have been created but not written to yet
+        Ok(pid) if pid.is_empty() => true,
+        Ok(pid) => Command::new("kill")
+            .args(["-0", &pid])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true),
+        Err(_) => false,
+    }
+}
+
+fn tag_matching(repo_dir: &Path, req: &version::VersionReq) -> Option<String> {
+    version::sorted_versions(&git_tags(repo_dir))
+        .into_iter()
+        .find(|v| !v.is_rc() && req.matches(v))
+        .map(|v| v.to_string())
+}
+
+// the newest tag in the same major series that is newer than what the install has
+fn newer_patch(id: &str, dir: &str, config: &Ini) -> Option<(OtpVersion, OtpVersion)> {
+    let installed = config::install_version(id, dir, config).or_else(|| {
+        config::lookup_install_option(id, "ref", config).and_then(|r| OtpVersion::parse(&r))
+    })?;
+    if installed.is_rc() {
+        return None;
+    }
+
+    let repo = config::lookup_install_option(id, "repo", config)?;
+    let cache_dir = config::lookup_cache_dir(config);
+    let repo_dir = Path::new(cache_dir).join("repos").join(repo);
+    if !repo_dir.is_dir() {
+        return None;
+    }
+
+    let newest = version::sorted_versions(&git_tags(&repo_dir))
+        .into_iter()
+        .find(|v| !v.is_rc() && v.major() == installed.major())?;
+    if newest > installed {
+        Some((installed, newest))
+    } else {
+        None
+    }
+}
+
+pub fn outdated(config: Ini) {
+    let erlangs = config::get_erlangs(&config);
+    let mut any_outdated = false;
+    for (id, dir) in erlangs {
+        if let Some((installed, newest)) = newer_patch(id, dir, &config) {
+            any_outdated = true;
+            println!("{}: {} -> {}", id, installed, newest);
+        }
+    }
+
+    if !any_outdated {
+        println!("All installs are up to date with their fetched repos.");
+    }
+}
+
+pub fn upgrade(
+    bin_path: PathBuf,
+    id: &str,
+    new_id: Option<String>,
+    set_default: bool,
+    update_projects: bool,
+    config_file: &str,
+    config: Ini,
+) {
+    let dir = config::lookup("erlangs", id.to_string(), &config).unwrap_or_else(|| {
+        error!("{} is not a configured Erlang install", id);
+        process::exit(1)
+    });
+    let (installed, newest) = match newer_patch(id, &dir, &config) {
+        Some(versions) => versions,
+        None => {
+            info!(
+                "{} is up to date, run `erlup fetch` to get the latest tags",
+                id
+            );
+            return;
+        }
+    };
+
+    let repo = config::lookup_install_option(id, "repo", &config).unwrap();
+    let repo_url = config::lookup("repos", repo.clone(), &config).unwrap_or_else(|| {
+        error!("Repo {} not found in config", repo);
+        process::exit(1)
+    });
+    let tag = newest.to_string();
+    let new_id = new_id.unwrap_or(tag.clone());
+    info!("Upgrading {} from {} to {}", id, installed, tag);
+
+    // built with the same --configure-opt and --flavor as the install it replaces
+    let configure_opts = config::lookup_install_option(id, "configure_opts", &config)
+        .and_then(|opts| shell_words::split(&opts).ok())
+        .unwrap_or_default();
+    let flavors: Vec<Flavor> = config::lookup_install_option(id, "flavors", &config)
+        .map(|flavors| {
+            flavors
+                .split(',')
+                .filter_map(|f| Flavor::from_str(f.trim(), true).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let healthy = run(
+        bin_path,
+        tag,
+        Some(new_id.clone()),
+        repo,
+        repo_url,
+        false,
+        false,
+        false,
+        &configure_opts,
+        &flavors,
+        config_file,
+        config.clone(),
+    );
+    if !healthy {
+        error!(
+            "{} is broken, the default and projects still use {}",
+            new_id, id
+        );
+        process::exit(1);
+    }
+
+    if set_default && config::lookup("erlup", "default".to_string(), &config).as_deref() == Some(id)
+    {
+        config::set_default(&new_id);
+    }
+
+    if update_projects {
+        for (project_dir, project_id) in config::get_projects(&config) {
+            if project_id == id && Path::new(project_dir).join("erlup.config").exists() {
+                config::set_project_erlang(Path::new(project_dir), &new_id, config_file);
+                info!("Updated {} to use {}", project_dir, new_id);
+            }
+        }
+        // projects never switched but whose erlup.config names the id, those
+        // with a requirement or detecting their Erlang pick the new one anyway
+        for project_dir in config::resolved_to(id, &config) {
+            if config::get_projects(&config)
+                .iter()
+                .all(|(switched, _)| *switched != project_dir)
+                && config::project_erlang(&project_dir).as_deref() == Some(id)
+            {
+                config::set_project_erlang(Path::new(&project_dir), &new_id, config_file);
+                info!("Updated {} to use {}", project_dir, new_id);
+            }
+        }
+    }
+}
+
+/// The directory an install's files are in, `otps/<id>` unless its configured
+/// dist dir says otherwise. Linked installs only have their links there.
+pub fn install_dir(id: &str, config: &Ini) -> PathBuf {
+    let otps_dir = Path::new(config::lookup_cache_dir(config)).join("otps");
+    config::lookup("erlangs", id.to_string(), config)
+        .as_ref()
+        .and_then(|d| Path::new(d).parent())
+        .filter(|d| d.starts_with(&otps_dir))
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| otps_dir.join(id))
+}
+
+/// Deletes an install, its incremental build tree and its config entry, in that
+/// order so a failure never leaves a config entry pointing at a removed install.
+/// The default can only be deleted with `force` or when given a new default.
+pub fn delete(
+    id: String,
+    force: bool,
+    new_default: Option<&str>,
+    dry_run: bool,
+    config_file: &str,
+    config: Ini,
+) {
+    let dir = &config::lookup_cache_dir(&config);
+
+    let dist_dir = config::lookup("erlangs", id.clone(), &config);
+    let install_dir = install_dir(&id, &config);
+    if dist_dir.is_none() && !install_dir.exists() {
+        error!("{} is not a configured Erlang install", id);
+        process::exit(1);
+    }
+
+    debug!("deleting {} at {}:", id, install_dir.display());
+
+    // the default may also be a version requirement that picks this install
+    let default = config::lookup("erlup", "default".to_string(), &config);
+    let is_default = match default.as_deref() {
+        Some(default) if default == id => true,
+        Some(default) => dist_dir.is_some() && config::lookup_erl(default, &config) == dist_dir,
+        None => false,
+    };
+
+    // an alias of the install goes with it, a default naming either is unset
+    let default_removed = match default.as_deref() {
+        Some(default) => {
+            default == id
+                || config::aliases_of(&id, &config)
+                    .iter()
+                    .any(|a| a == default)
+        }
+        None => false,
+    };
+
+    if let Some(new_default) = new_default {
+        if !is_default {
+            error!(
+                "{} isn't the default Erlang, --new-default only replaces the default",
+                id
+            );
+            process::exit(1);
+        }
+        if new_default == id
+            || config::lookup("erlangs", new_default.to_string(), &config).is_none()
+        {
+            error!(
+                "{} is not a configured Erlang install that can replace {} as default",
+                new_default, id
+            );
+            process::exit(1);
+        }
+    } else if is_default && !force {
+        error!(
+            "{} is the default Erlang, every command run outside a project would fail without it",
+            id
+        );
+        error!("Give a new default with --new-default <id> or use --force to delete it anyway");
+        process::exit(1);
+    }
+
+    for project_dir in config::projects_using(&id, &config) {
+        info!(
+            "{} is used by the project {}, switch it to another Erlang with `erlup switch`",
+            id, project_dir
+        );
+    }
+
+    // switching the default first means it never points at a half deleted install
+    if let Some(new_default) = new_default {
+        if dry_run {
+            info!("Would set the default Erlang to {}", new_default);
+        } else {
+            config::update_default(Some(new_default), config_file);
+            info!("Default Erlang now {}", new_default);
+        }
+    }
+
+    // delete the install dir from disk before the config entry, so a failure
+    // doesn't leave files behind that nothing points to
+    remove_dir(&install_dir, dry_run);
+
+    // and the build tree of its incremental builds, git prunes the worktree on its next add
+    remove_dir(&Path::new(dir).join("worktrees").join(&id), dry_run);
+
+    for alias in config::aliases_of(&id, &config) {
+        match dry_run {
+            true => info!("Would remove alias {}", alias),
+            false => info!("Removing alias {}", alias),
+        }
+    }
+
+    if dry_run {
+        info!("Would remove {} from the config", id);
+        if new_default.is_none() && default_removed {
+            info!("Would unset the default Erlang");
+        }
+        return;
+    }
+
+    // remove the entry from config
+    if new_default.is_none() && default_removed {
+        config::update_default(None, config_file);
+        info!("Unset the default Erlang, set a new one with `erlup default <id>`");
+    }
+    config::delete(id, config_file);
+}
+
+/// Renames an install, moving it from `otps/<old>` to `otps/<new>`, and updates
+/// the default, aliases and projects that used the old id.
+pub fn rename(old: &str, new: &str, config_file: &str, config: Ini) {
+    let dist_dir = config::lookup("erlangs", old.to_string(), &config).unwrap_or_else(|| {
+        error!("{} is not a configured Erlang install", old);
+        process::exit(1)
+    });
+    if config::lookup("erlangs", new.to_string(), &config).is_some()
+        || config::lookup("aliases", new.to_string(), &config).is_some()
+    {
+        error!("{} is already the id or alias of an install", new);
+        process::exit(1);
+    }
+
+    let dir = config::lookup_cache_dir(&config);
+    let otps_dir = Path::new(dir).join("otps");
+    let old_install = otps_dir.join(old);
+    let new_install = otps_dir.join(new);
+    // installs erlup built itself live in otps/<id>, linked installs only have
+    // their links to the executables there
+    let built = Path::new(&dist_dir) == old_install.join("dist");
+    if old_install.exists() {
+        if new_install.exists() {
+            error!("Directory for {} already exists: {:?}", new, new_install);
+            process::exit(1);
+        }
+        std::fs::rename(&old_install, &new_install).unwrap_or_else(|e| {
+            error!(
+                "unable to move {:?} to {:?}: {}",
+                old_install, new_install, e
+            );
+            process::exit(1)
+        });
+    }
+    let mut relocated = true;
+    let dist_dir = if built {
+        let new_dist = new_install.join("dist");
+        relocated = relocate(&new_dist);
+        verify::write_manifest(&new_dist);
+        // the links next to dist still point into the old directory
+        if let Ok(entries) = read_dir(&new_install) {
+            for entry in entries.filter_map(Result::ok) {
+                if entry.file_type().map(|t| t.is_symlink()).unwrap_or(false) {
+                    let _ = remove_file(entry.path());
+                }
+            }
+        }
+        setup_links(&new_install, &new_dist);
+        new_dist
+    } else {
+        PathBuf::from(dist_dir)
+    };
+
+    // the worktree of incremental builds follows, it has to be configured again
+    // though since its install prefix is the old directory
+    let old_worktree = worktree_dir(old, &config);
+    if old_worktree.exists() {
+        let new_worktree = worktree_dir(new, &config);
+        let _ = create_dir_all(new_worktree.parent().unwrap());
+        let output = Command::new("git")
+            .args(["worktree", "move", old_worktree.to_str().unwrap()])
+            .arg(&new_worktree)
+            .current_dir(&old_worktree)
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                remove_dir(old_worktree.parent().unwrap(), false);
+                let _ = remove_file(new_worktree.with_file_name("configured"));
+            }
+            _ => info!(
+                "Unable to move the incremental build tree {}, delete it or build {} again with --incremental",
+                old_worktree.display(),
+                old
+            ),
+        }
+    }
+
+    config::rename_install(old, new, dist_dir.to_str().unwrap(), config_file);
+    // its start scripts still run the old directory's emulator
+    if !relocated {
+        config::update_install_option(new, "status", "broken", config_file);
+        error!("{} is marked broken, build it again to repair it", new);
+    }
+
+    let mut project_dirs: Vec<String> = config::get_projects(&config)
+        .iter()
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect();
+    for project_dir in config::resolved_to(old, &config) {
+        if !project_dirs.contains(&project_dir) {
+            project_dirs.push(project_dir);
+        }
+    }
+    for project_dir in project_dirs {
+        if config::project_erlang(&project_dir).as_deref() == Some(old) {
+            config::set_project_erlang(Path::new(&project_dir), new, config_file);
+            info!("Updated {} to use {}", project_dir, new);
+        }
+    }
+
+    info!("Renamed {} to {}", old, new);
+}
+
+// OTP's start scripts have the install's absolute path in them, its Install
+// script writes them again for the new location
+fn relocate(dist_dir: &Path) -> bool {
+    let root = erlang_root(dist_dir);
+    let install = root.join("Install");
+    if !install.exists() {
+        return true;
+    }
+
+    let output = Command::new(&install)
+        .arg("-minimal")
+        .arg(&root)
+        .current_dir(&root)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            error!(
+                "updating the start scripts in {} failed: {}",
+                root.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+            false
+        }
+        Err(e) => {
+            error!("unable to run {}: {}", install.display(), e);
+            false
+        }
+    }
+}
+
+/// Whether `id` is free to be used for a new install.
+pub fn id_available(id: &str, config: &Ini) -> bool {
+    let dir = config::lookup_cache_dir(config);
+    config::lookup("erlangs", id.to_string(), config).is_none()
+        && config::lookup("aliases", id.to_string(), config).is_none()
+        && !Path::new(dir).join("otps").join(id).exists()
+}
+
+/// The canonical path of an Erlang installed some other way, as long as it has
+/// an executable `bin/erl`.
+pub fn external_install(path: &Path) -> Result<PathBuf, String> {
+    let dist_dir = path
+        .canonicalize()
+        .map_err(|e| format!("unable to find {}: {}", path.display(), e))?;
+    match metadata(dist_dir.join("bin").join("erl")) {
+        Ok(m) if m.is_file() && m.permissions().mode() & 0o111 != 0 => Ok(dist_dir),
+        _ => Err(format!(
+            "{} is not an Erlang install, it has no executable bin/erl",
+            dist_dir.display()
+        )),
+    }
+}
+
+/// Registers an Erlang installed some other way, eg. by a distro package or kerl,
+/// under `id` without copying it. Its executables are linked from `otps/<id>`.
+pub fn link(id: &str, path: &Path, config_file: &str, config: &Ini) {
+    if !id_available(id, config) {
+        error!("{} is already the id or alias of an install", id);
+        process::exit(1);
+    }
+    let dist_dir = external_install(path).unwrap_or_else(|e| {
+        error!("{}", e);
+        process::exit(1)
+    });
+
+    let install_dir = Path::new(config::lookup_cache_dir(config))
+        .join("otps")
+        .join(id);
+    let _ = create_dir_all(&install_dir);
+    setup_links(&install_dir, &dist_dir);
+
+    register_install(id, &dist_dir, config_file, config);
+    config::update_install_option(id, "linked", "true", config_file);
+    info!("Linked {} to {}", id, dist_dir.display());
+}
+
+/// Copies an Erlang installed some other way to `otps/<id>/dist`, after which
+/// it's independent of the original.
+pub fn copy_install(id: &str, path: &Path, config_file: &str, config: &Ini) {
+    if !id_available(id, config) {
+        error!("{} is already the id or alias of an install", id);
+        process::exit(1);
+    }
+    let from = external_install(path).unwrap_or_else(|e| {
+        error!("{}", e);
+        process::exit(1)
+    });
+
+    let install_dir = Path::new(config::lookup_cache_dir(config))
+        .join("otps")
+        .join(id);
+    let dist_dir = install_dir.join("dist");
+    if let Err(e) = copy_dir(&from, &dist_dir) {
+        error!(
+            "unable to copy {} to {}: {}",
+            from.display(),
+            dist_dir.display(),
+            e
+        );
+        let _ = remove_dir_all(&install_dir);
+        process::exit(1);
+    }
+    let relocated = relocate(&dist_dir);
+    setup_links(&install_dir, &dist_dir);
+
+    register_install(id, &dist_dir, config_file, config);
+    if !relocated {
+        config::update_install_option(id, "status", "broken", config_file);
+        error!("{} is marked broken, copy it again to repair it", id);
+    }
+    verify::write_manifest(&dist_dir);
+    info!("Copied {} to {}", from.display(), dist_dir.display());
+}
+
+// smoke tests an install that wasn't built by erlup and adds it to the config
+fn register_install(id: &str, dist_dir: &Path, config_file: &str, config: &Ini) {
+    let required_apps = config::required_apps("default", config);
+    let problems = verify::smoke_test(dist_dir, None, &required_apps);
+    for problem in problems.iter() {
+        println!(" {} {}", WARNING, problem);
+    }
+
+    config::update(id.to_string(), dist_dir.to_str().unwrap(), config_file);
+    let status = if problems.is_empty() { "ok" } else { "broken" };
+    config::update_install_option(id, "status", status, config_file);
+    if let Some(vsn) = version::read_otp_version(dist_dir) {
+        config::update_install_option(id, "otp_version", &vsn, config_file);
+    }
+}
+
+// copies a directory tree keeping its symlinks, and file permissions as
+// `std::fs::copy` does
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+    create_dir_all(to)?;
+    for entry in read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            fs::symlink(read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_dir(dir: &Path, dry_run: bool) {
+    if !dir.exists() {
+        return;
+    }
+
+    if dry_run {
+        info!("Would delete {}", dir.display());
+        return;
+    }
+
+    std::fs::remove_dir_all(dir).unwrap_or_else(|e| {
+        error!("unable to delete {} due to {}", dir.display(), e);
+        process::exit(1);
+    });
+}
+
+pub fn info(id: &str, config: Ini) {
+    let dist_dir = config::lookup("erlangs", id.to_string(), &config).unwrap_or_else(|| {
+        error!("{} is not a configured Erlang install", id);
+        process::exit(1)
+    });
+    let dist_dir = Path::new(&dist_dir);
+
+    println!("id: {}", id);
+    println!("dir: {}", dist_dir.display());
+    if config::is_linked(id, &config) {
+        println!("linked: not built or managed by erlup");
+    }
+    let aliases = config::aliases_of(id, &config);
+    if !aliases.is_empty() {
+        println!("aliases: {}", aliases.join(", "));
+    }
+    if let Some(status) = config::lookup_install_option(id, "status", &config) {
+        println!("status: {}", status);
+    }
+    match version::read_otp_version(dist_dir) {
+        Some(vsn) => println!("otp version: {}", vsn),
+        None => println!("otp version: unknown"),
+    }
+    let flavors = installed_flavors(dist_dir);
+    if flavors.is_empty() {
+        println!("flavors: none");
+    } else {
+        println!("flavors: {}", flavors.join(", "));
+    }
+    if let Some(rebar3) = config::lookup_install_option(id, "rebar3", &config) {
+        println!("rebar3: {}", rebar3);
+    }
+}
+
+/// The directory with the `erts-*` and `releases` directories of an install,
+/// `lib/erlang` under the prefix it was installed to or, for some linked
+/// installs like a distro's `/usr/lib/erlang`, the install itself.
+pub fn erlang_root(dist_dir: &Path) -> PathBuf {
+    let root = dist_dir.join("lib").join("erlang");
+    if root.is_dir() {
+        root
+    } else {
+        dist_dir.to_path_buf()
+    }
+}
+
+// the regular emulator is beam.smp, extra flavors are installed as beam.<flavor>.smp
+fn installed_flavors(dist_dir: &Path) -> Vec<String> {
+    let pattern = erlang_root(dist_dir).join("erts-*/bin/beam.*.smp");
+    let mut flavors: Vec<String> = match glob(pattern.to_str().unwrap()) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .filter_map(|p| {
+                p.file_name()
+                    .and_then(|f| f.to_str())
+                    .and_then(|f| f.strip_prefix("beam."))
+                    .and_then(|f| f.strip_suffix(".smp"))
+                    .map(str::to_string)
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    flavors.sort();
+    flavors.dedup();
+    flavors
+}
+
+pub fn checkout(dir: &Path, repo_dir: &Path, vsn: &str, pb: &ProgressBar) {
+    let otp_tar = dir.join("otp.tar");
+    debug!("otp_tar={}", otp_tar.to_str().unwrap());
+    let output = Command::new("git")
+        .args(["archive", "-o", otp_tar.to_str().unwrap(), vsn])
+        .current_dir(repo_dir)
+        .output()
+        .unwrap_or_else(|e| {
+            error!("git archive failed: {}", e);
+            process::exit(1)
+        });
+
+    if !output.status.success() {
+        checkout_failed(dir, vsn, &String::from_utf8_lossy(&output.stderr), pb);
+    }
+
+    if let Err(e) = File::open(otp_tar).and_then(|f| Archive::new(f).unpack(dir)) {
+        checkout_failed(dir, vsn, &e.to_string(), pb);
+    }
+}
+
+// exiting skips the temporary tree's cleanup, there's nothing in it worth keeping
+fn checkout_failed(dir: &Path, vsn: &str, reason: &str, pb: &ProgressBar) -> ! {
+    pb.println(format!(" {} Checking out {}", FAIL, vsn));
+    error!("checkout of {} failed: {}", vsn, reason);
+    let _ = remove_dir_all(dir);
+    process::exit(1)
+}
+
+// the worktree is created on the first incremental build and moved to the new
+// commit on later ones, keeping the build output of the previous build
+fn checkout_worktree(dir: &Path, repo_dir: &Path, commit: &str, pb: &ProgressBar) {
+    let output = if dir.exists() {
+        // a build can modify tracked files, which would make a plain checkout
+        // refuse to switch commits, the untracked build output is kept
+        Command::new("git")
+            .args(["checkout", "--force", "--detach", commit])
+            .current_dir(dir)
+            .output()
+    } else {
+        // forget worktrees whose directories were removed by hand
+        let _ = Command::new("git")
+            .args(["worktree", "prune"])
+            .current_dir(repo_dir)
+            .output();
+        Command::new("git")
+            .args(["worktree", "add", "--detach", dir.to_str().unwrap(), commit])
+            .current_dir(repo_dir)
+            .output()
+    }
+    .unwrap_or_else(|e| {
+        error!("git checkout failed: {}", e);
+        process::exit(1)
+    });
+
+    if !output.status.success() {
+        pb.println(format!(" {} Checking out {}", FAIL, commit));
+        error!(
+            "checkout of {} in {} failed: {}",
+            commit,
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        process::exit(1);
+    }
+}
+
+pub fn setup_links(install_dir: &Path, dist_dir: &Path) {
+    for &b in BINS.iter().chain([rebar3::BIN].iter()) {
+        let f = Path::new(b).file_name().unwrap();
+        let bin = dist_dir.join(b);
+        let paths = glob(bin.to_str().unwrap()).unwrap();
+
+        match paths.last() {
+            Some(x) => {
+                let link = install_dir.join(f);
+                let _ = fs::symlink(x.unwrap().to_str().unwrap(), link);
+            }
+            None => debug!("file to link not found: {}", f.to_str().unwrap()),
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build(
+    repo_dir: PathBuf,
+    install_dir: &Path,
+    vsn: String,
+    commit: &str,
+    user_configure_options0: &str,
+    flavors: &[Flavor],
+    required_apps: &[String],
+    build_dir: BuildDir,
+    keep_build_dir: bool,
+) -> bool {
+    let started = Instant::now();
+    let pb = spinner();
+
+    let num_cpus = num_cpus::get().to_string();
+    let src_dir = build_dir.path();
+
+    pb.set_message(format!("Checking out {}", vsn));
+
+    match build_dir {
+        BuildDir::Temp(_) => checkout(src_dir, &repo_dir, commit, &pb),
+        BuildDir::Worktree(_) => checkout_worktree(src_dir, &repo_dir, commit, &pb),
+    }
+    let _ = std::fs::create_dir_all(repo_dir);
+    let _ = std::fs::create_dir_all(install_dir);
+
+    pb.println(format!(
+        " {} Checking out {} (done in {})",
+        CHECKMARK,
+        vsn,
+        HumanDuration(started.elapsed())
+    ));
+    debug!("build dir: {:?}", src_dir);
+
+    let dist_dir = install_dir.join("dist");
+
+    // split the configure options into a vector of String in a shell sensitive way
+    // eg.
+    //  from:
+    //      user_configure_options0: --without-wx --without-observer --without-odbc --without-debugger --without-et --enable-builtin-zlib --without-javac CFLAGS="-g -O2 -march=native"
+    //  to:
+    //      user_configure_options: ["--without-wx", "--without-observer", "--without-odbc", "--without-debugger", "--without-et", "--enable-builtin-zlib", "--without-javac", "CFLAGS=-g -O2 -march=native"]
+    let mut user_configure_options: Vec<String> = shell_words::split(user_configure_options0)
+        .unwrap_or_else(|e| {
+            error!("bad configure options {}\n\t{}", user_configure_options0, e);
+            process::exit(1);
+        });
+    // basic configure options must always include a prefix
+    let mut configure_options = vec![
+        "--prefix".to_string(),
+        dist_dir.to_str().unwrap().to_string(),
+    ];
+    // append the user defined options
+    configure_options.append(&mut user_configure_options);
+
+    // an incremental build tree that was already configured with the same
+    // options only needs `make` to pick up the changed sources
+    let configured_file = src_dir.with_file_name("configured");
+    let configured = matches!(build_dir, BuildDir::Worktree(_))
+        && read_to_string(&configured_file).ok().as_deref() == Some(user_configure_options0);
+
+    // declare the build pipeline steps
+    let mut build_steps: Vec<BuildStep> = vec![];
+    if configured {
+        pb.println(format!(
+            " {} Reusing configured build tree {}",
+            CHECKMARK,
+            src_dir.display()
+        ));
+    } else {
+        build_steps.extend([
+            BuildStep::Exec("./otp_build", vec!["autoconf".to_string()]),
+            BuildStep::Exec("./configure", configure_options),
+        ]);
+        if let BuildDir::Worktree(_) = build_dir {
+            // remember the options once configure succeeded, a failed make
+            // afterwards doesn't require configuring again
+            build_steps.push(BuildStep::Check(Box::new(|context| {
+                if let BuildResult::Success = context.build_status {
+                    let _ = write(&configured_file, user_configure_options0);
+                }
+                CheckResult::Success
+            })));
+        }
+    }
+    build_steps.extend([
+        BuildStep::Check(Box::new(|context| {
+            check_skipped_apps(context.src_dir, required_apps)
+        })),
+        BuildStep::Exec("make", vec!["-j".to_string(), num_cpus.to_string()]),
+    ]);
+    // extra emulator flavors are built after the regular emulator so
+    // `make install` picks them up and installs them alongside it
+    for flavor in flavors {
+        build_steps.push(BuildStep::Exec(
+            "make",
+            vec![
+                "-j".to_string(),
+                num_cpus.to_string(),
+                format!("emu_type={}", flavor.emu_type()),
+            ],
+        ));
+    }
+    build_steps.extend([
+        BuildStep::Exec(
+            "make",
+            vec![
+                "-j".to_string(),
+                num_cpus.to_string(),
+                "docs".to_string(),
+                "DOC_TARGETS=chunks".to_string(),
+            ],
+        ),
+        // after `make` we'll already know if this build failed or not, this allows us
+        // to make a better decision in wether to delete the installation dir should there
+        // be one.
+        BuildStep::Check(Box::new(|context| {
+            match context.build_status {
+                BuildResult::Fail => {
+                    debug!("build has failed, aborting install to prevent overwriting a possibly working installation dir");
+                    // this build has failed, we won't touch the previously existing install
+                    // dir, for all we know it could hold a previously working installation
+                    CheckResult::Fail("Build failed, not installing".to_string())
+                }
+                // if the build succeeded, then we check for an already existing
+                // install dir, if we find one we can delete it and proceed to the
+                // install phase
+                BuildResult::Success => {
+                    // is install dir empty? courtesy of StackOverflow
+                    let is_empty = context
+                        .install_dir
+                        .read_dir()
+                        .map(|mut i| i.next().is_none())
+                        .unwrap_or(false);
+                    if is_empty {
+                        // it's fine, it was probably us who created the dir just a moment ago,
+                        // that's why it's empty
+                        CheckResult::Success
+                    } else {
+                        debug!("found a non empty installation dir after a successful build, removing it");
+                        // dir is not empty, maybe a working installation is already there,
+                        // delete the whole thing and proceed, we can go ahead with this
+                        // because we know we have a working build in our hands
+                        let _ = std::fs::remove_dir_all(context.install_dir);
+                        CheckResult::Success
+                    }
+                }
+            }
+        })),
+        BuildStep::Exec(
+            "make",
+            vec![
+                "-j".to_string(),
+                num_cpus.to_string(),
+                "install".to_string(),
+            ],
+        ),
+        BuildStep::Exec(
+            "make",
+            vec![
+                "-j".to_string(),
+                num_cpus.to_string(),
+                "install-docs".to_string(),
+            ],
+        ),
+    ]);
+    // execute them sequentially
+    let mut build_status = BuildResult::Success;
+    for step in build_steps.iter() {
+        let step_started = Instant::now();
+
+        match step {
+            BuildStep::Exec(command, args) => {
+                // it only takes one exec command to fail for the build status
+                // to be fail as well, a subsequent check build step can optionally decide
+                // to fail the pipeline
+                match exec(command, args, src_dir, step_started, &pb) {
+                    Ok(BuildResult::Success) => (),
+                    Ok(BuildResult::Fail) => build_status = BuildResult::Fail,
+                    Err(e) => {
+                        error!("build failed: {}", e);
+                        abort_build(build_dir, &pb)
+                    }
+                }
+            }
+            BuildStep::Check(fun) => {
+                let context = CheckContext {
+                    src_dir,
+                    install_dir,
+                    build_status,
+                };
+                match fun(&context) {
+                    CheckResult::Success => {
+                        debug!("success");
+                    }
+                    CheckResult::Warning(warning) => {
+                        debug!("{}", warning);
+                        for line in warning.lines() {
+                            pb.println(format!(" {} {}", WARNING, line));
+                        }
+                    }
+                    CheckResult::Fail(failure) => {
+                        // abort
+                        for line in failure.lines() {
+                            pb.println(format!(" {} {}", FAIL, line));
+                        }
+                        abort_build(build_dir, &pb)
+                    }
+                }
+            }
         }
     }
+    // `make install` and `install-docs` come after the last check
+    if let BuildResult::Fail = build_status {
+        abort_build(build_dir, &pb)
+    }
+    if keep_build_dir {
+        pb.println(format!(
+            " {} Build tree kept at {}",
+            CHECKMARK,
+            build_dir.keep().display()
+        ));
+    } else {
+        // By closing the `TempDir` explicitly, we can check that it has
+        // been deleted successfully. If we don't close it explicitly,
+        // the directory will still be deleted when `tmp_dir` goes out
+        // of scope, but we won't know whether deleting the directory
+        // succeeded. An incremental build's worktree is kept for the next build.
+        drop(build_dir);
+    }
 
     pb.set_message("Setting up symlinks");
-    setup_links(install_dir);
+    setup_links(install_dir, &install_dir.join("dist"));
     pb.println(format!(" {} {}", CHECKMARK, "Setting up symlinks"));
 
-    pb.finish_and_clear();
-    println!(
-        "{} build in {}",
-        style("Finished").green().bold(),
-        HumanDuration(started.elapsed())
+    pb.set_message("Verifying install");
+    let expected_version = verify::expected_otp_version(&vsn);
+    let problems = verify::smoke_test(
+        &install_dir.join("dist"),
+        expected_version.as_deref(),
+        required_apps,
     );
+    if problems.is_empty() {
+        pb.println(format!(" {} {}", CHECKMARK, "Verifying install"));
+    } else {
+        pb.println(format!(" {} {}", FAIL, "Verifying install"));
+        for problem in problems.iter() {
+            pb.println(format!(" {} {}", WARNING, problem));
+        }
+    }
+
+    // like the progress, the summary goes to stderr so a build started by a
+    // shim doesn't end up in the output of the command it runs
+    pb.finish_and_clear();
+    if problems.is_empty() {
+        eprintln!(
+            "{} build in {}",
+            style("Finished").green().bold(),
+            HumanDuration(started.elapsed())
+        );
+    } else {
+        eprintln!(
+            "{} build in {}, but the install is broken",
+            style("Finished").yellow().bold(),
+            HumanDuration(started.elapsed())
+        );
+    }
+
+    problems.is_empty()
+}
+
+/// Puts an install's `bin` first in PATH, for scripts like Elixir's or rebar3's
+/// bootstrap that run whichever `erl` or `escript` they find there.
+pub fn put_erlang_on_path(erl_dir: &str) {
+    let mut paths = vec![Path::new(erl_dir).join("bin")];
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(env::split_paths(&path));
+    }
+    if let Ok(path) = env::join_paths(paths) {
+        env::set_var("PATH", path);
+    }
+}
+
+/// The spinner the build steps are shown with.
+pub fn spinner() -> ProgressBar {
+    let spinner_style = ProgressStyle::default_spinner()
+        .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈ ")
+        .template("{prefix:.bold.dim} {spinner} {wide_msg}")
+        .unwrap();
+
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(spinner_style);
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
+
+// a failed build's tree is kept so its logs, like config.log, can be looked at
+pub fn abort_build(build_dir: BuildDir, pb: &ProgressBar) -> ! {
+    pb.finish_and_clear();
+    match build_dir {
+        BuildDir::Temp(_) => {
+            error!("Build tree kept at {}", build_dir.keep().display());
+            error!("Remove it with `erlup clean` when done with it");
+        }
+        BuildDir::Worktree(_) => error!("Build tree is at {}", build_dir.path().display()),
+    }
+    process::exit(1)
 }
 
-fn exec(
+// an error means the command couldn't be run at all, a command that ran and
+// failed is a failed build step
+pub fn exec(
     command: &str,
     args: &Vec<String>,
     dir: &Path,
     started_ts: Instant,
     pb: &ProgressBar,
-) -> BuildResult {
+) -> std::io::Result<BuildResult> {
     debug!("Running {} {:?}", command, args);
     pb.set_message(format!("{} {}", command, args.join(" ")));
     let output = Command::new(command)
         .args(args)
         .current_dir(dir)
         .output()
-        .unwrap_or_else(|e| {
-            pb.println(format!(" {} {} {}", FAIL, command, args.join(" ")));
-            error!("build failed: {}", e);
-            process::exit(1)
-        });
+        .inspect_err(|_| pb.println(format!(" {} {} {}", FAIL, command, args.join(" "))))?;
 
     debug!("stdout: {}", String::from_utf8_lossy(&output.stdout));
     debug!("stderr: {}", String::from_utf8_lossy(&output.stderr));
@@ -611,18 +1936,86 @@ fn exec(
                 args.join(" "),
                 HumanDuration(started_ts.elapsed())
             ));
-            BuildResult::Success
+            Ok(BuildResult::Success)
         }
         false => {
             error!("stdout: {}", String::from_utf8_lossy(&output.stdout));
             pb.println(format!(" {} {} {}", FAIL, command, args.join(" ")));
-            BuildResult::Fail
+            Ok(BuildResult::Fail)
         }
     }
 }
 
-fn has_openssl(src_dir: &Path) -> bool {
-    // check that lib/crypto/SKIP doesn't exist,
-    // if it does it means something went wrong with OpenSSL
-    !src_dir.join("./lib/crypto/SKIP").exists()
+// configure writes lib/<app>/SKIP, containing the reason, for each application it
+// won't be able to build, eg. lib/crypto/SKIP when no usable OpenSSL was found
+fn skipped_apps(src_dir: &Path) -> Vec<(String, String)> {
+    let pattern = src_dir.join("lib/*/SKIP");
+    let mut skipped: Vec<(String, String)> = match glob(pattern.to_str().unwrap()) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .filter_map(|skip| {
+                let app = skip.parent()?.file_name()?.to_str()?.to_string();
+                let reason = read_to_string(&skip).unwrap_or_default();
+                Some((
+                    app,
+                    reason.split_whitespace().collect::<Vec<&str>>().join(" "),
+                ))
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    skipped.sort();
+    skipped
+}
+
+fn check_skipped_apps(src_dir: &Path, required_apps: &[String]) -> CheckResult {
+    // a required app that isn't in lib/ at all is most likely a typo in the config
+    let unknown: Vec<&str> = required_apps
+        .iter()
+        .filter(|app| !src_dir.join("lib").join(app).is_dir())
+        .map(String::as_str)
+        .collect();
+    if !unknown.is_empty() {
+        return CheckResult::Fail(format!(
+            "required applications not found in {}: {}",
+            src_dir.join("lib").display(),
+            unknown.join(", ")
+        ));
+    }
+
+    let skipped = skipped_apps(src_dir);
+    if skipped.is_empty() {
+        return CheckResult::Success;
+    }
+
+    let mut lines = vec![];
+    for (app, reason) in skipped.iter() {
+        let reason = match reason.as_str() {
+            "" => "no reason given",
+            r => r,
+        };
+        lines.push(format!(
+            "`{}` application will not be built: {}",
+            app, reason
+        ));
+        if app == "crypto" {
+            lines
+                .push("please specify an OpenSSL with the --with-ssl configure option".to_string());
+        }
+    }
+
+    let missing: Vec<&str> = required_apps
+        .iter()
+        .filter(|required| skipped.iter().any(|(app, _)| app == *required))
+        .map(String::as_str)
+        .collect();
+    if missing.is_empty() {
+        CheckResult::Warning(lines.join("\n"))
+    } else {
+        lines.push(format!(
+            "required applications can not be built: {}",
+            missing.join(", ")
+        ));
+        CheckResult::Fail(lines.join("\n"))
+    }
 }
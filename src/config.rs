@@ -1,8 +1,14 @@
 use ini::Ini;
+use std::env;
 use std::fs::*;
 use std::path::*;
 use std::process;
 
+use crate::build;
+use crate::detect;
+use crate::version;
+use crate::version::{OtpVersion, VersionReq};
+
 fn home_config_file() -> String {
     let config_dir = match dirs::config_dir() {
         Some(d) => d,
@@ -51,7 +57,13 @@ pub fn list() {
     if let Some(erlup) = config.section(Some("erlangs")) {
         for s in erlup {
             let (k, v) = s;
-            println!("{} -> {}", k, v);
+            match lookup_install_option(k, "status", &config).as_deref() {
+                Some("broken") => println!("{} -> {} (broken)", k, v),
+                _ => println!("{} -> {}", k, v),
+            }
+        }
+        for (alias, id) in get_aliases(&config) {
+            println!("{} -> {} (alias)", alias, id);
         }
     } else {
         println!("No Erlang releases installed.");
@@ -59,43 +71,185 @@ pub fn list() {
 }
 
 pub fn erl_to_use() -> String {
-    let (_, config) = home_config();
+    let (config_file, config) = home_config();
 
-    let erl_to_use = match Ini::load_from_file("erlup.config") {
-        Ok(cwd_config) => {
-            debug!("Found ./erlup.config");
-            match lookup("config", "erlang".to_string(), &cwd_config) {
-                Some(entry) => entry.clone(),
-                None => {
-                    error!("No Erlang entry found in erlup.config");
-                    error!("Delete or update the config file");
-                    process::exit(1)
-                }
-            }
+    // an erlup.config may only pick the Elixir, leaving the Erlang to the default
+    let mut from_project = true;
+    let erl_to_use = match project_erlang(".") {
+        Some(entry) => {
+            debug!("Found Erlang in ./erlup.config");
+            entry
         }
-        Err(_) => {
-            debug!("No ./erlup.config found, going to default");
-            match lookup("erlup", "default".to_string(), &config) {
-                Some(entry) => entry.clone(),
-                None => {
-                    error!("No default Erlang set. Use `erlup default <id>`");
-                    process::exit(1)
+        None => match detected_erl(&config) {
+            Some(id) => id,
+            None => {
+                from_project = false;
+                debug!("No Erlang in ./erlup.config, going to default");
+                match lookup("erlup", "default".to_string(), &config) {
+                    Some(entry) => entry.clone(),
+                    None => {
+                        error!("No default Erlang set. Use `erlup default <id>`");
+                        process::exit(1)
+                    }
                 }
             }
-        }
+        },
     };
 
     debug!("Using Erlang with id {}", erl_to_use);
-    match lookup("erlangs", erl_to_use.to_string(), &config) {
-        Some(erl) => erl.clone(),
+    if let Some(erl) = lookup_erl(&erl_to_use, &config) {
+        if from_project {
+            record_resolved(&erl, &config_file, &config);
+        }
+        return erl;
+    }
+
+    if auto_install(&config) {
+        info!("Erlang {} is not installed, building it", erl_to_use);
+        let erl = build::auto_install(&erl_to_use, &config_file, config);
+        if from_project {
+            record_resolved(&erl, &config_file, &read_config(config_file.clone()));
+        }
+        return erl;
+    }
+
+    match VersionReq::parse(&erl_to_use) {
+        Some(req) => {
+            error!("No installed Erlang matches version {}", erl_to_use);
+            let broken = broken_matching_installs(&req, &config);
+            if !broken.is_empty() {
+                error!("Skipped broken installs that match: {}", broken.join(", "));
+            }
+            error!("Build one with `erlup build <tag>`, eg. `erlup build latest:<major>`");
+        }
         None => {
             error!(
                 "No directory found for Erlang with id {} in config",
                 erl_to_use
             );
-            process::exit(1)
         }
     }
+    process::exit(1)
+}
+
+// which install a project resolved to through its erlup.config or detection, in
+// `[resolved]`, lets upgrade, delete and gc know about projects `erlup switch`
+// never registered. It's only written when it changed.
+fn record_resolved(erl_dir: &str, config_file: &str, config: &Ini) {
+    let (cwd, id) = match (env::current_dir(), install_id(erl_dir, config)) {
+        (Ok(cwd), Some(id)) => (cwd, id),
+        _ => return,
+    };
+    let project_dir = cwd.to_str().unwrap().to_string();
+    if lookup("resolved", project_dir.clone(), config).as_deref() == Some(id.as_str()) {
+        return;
+    }
+
+    // shims run in parallel, eg. erlc by make, so the config is read again under
+    // the lock and replaced in one rename, never leaving a half written file
+    let _lock = build::Lock::acquire(PathBuf::from(format!("{}.lock", config_file)), None);
+    let mut config = match Ini::load_from_file(config_file) {
+        Ok(config) => config,
+        Err(_) => return,
+    };
+    if lookup("resolved", project_dir.clone(), &config).as_deref() == Some(id.as_str()) {
+        return;
+    }
+    config
+        .with_section(Some("resolved".to_owned()))
+        .set(project_dir, id);
+    let tmp = format!("{}.{}", config_file, process::id());
+    if config.write_to_file(&tmp).is_err() || rename(&tmp, config_file).is_err() {
+        let _ = remove_file(&tmp);
+    }
+}
+
+// project dirs and the install they last resolved to, see `record_resolved`
+pub fn get_resolved(config: &Ini) -> Vec<(&str, &str)> {
+    match config.section(Some("resolved")) {
+        Some(section) => section.iter().collect::<Vec<(&str, &str)>>(),
+        None => vec![],
+    }
+}
+
+/// The id of the install at `erl_dir`, a dir from `lookup_erl` or `erl_to_use`.
+pub fn install_id(erl_dir: &str, config: &Ini) -> Option<String> {
+    get_erlangs(config)
+        .into_iter()
+        .find(|(_, dir)| *dir == erl_dir)
+        .map(|(id, _)| id.to_string())
+}
+
+/// Finds the install directory for an id or alias or, when it's neither, the highest
+/// install matching it as a version requirement like `26` or `>= 25.3, < 27`.
+pub fn lookup_erl(erl_to_use: &str, config: &Ini) -> Option<String> {
+    if let Some(erl) = lookup("erlangs", erl_to_use.to_string(), config) {
+        return Some(erl);
+    }
+
+    if let Some(id) = lookup("aliases", erl_to_use.to_string(), config) {
+        debug!("Alias {} refers to id {}", erl_to_use, id);
+        return lookup("erlangs", id, config);
+    }
+
+    let req = VersionReq::parse(erl_to_use)?;
+    let (id, erl) = highest_matching_install(&req, config)?;
+    debug!("Requirement {} resolved to id {}", erl_to_use, id);
+    Some(erl)
+}
+
+// without an erlup.config the project's own files, like rebar.config's
+// minimum_otp_vsn, can pick the Erlang when `detect` is enabled in `[erlup]`
+fn detected_erl(config: &Ini) -> Option<String> {
+    if !lookup_bool("erlup", "detect", config).unwrap_or(false) {
+        return None;
+    }
+
+    let detected = detect::detect(Path::new("."));
+    let (id, req) = detect::compatible_install(&detected, config)?;
+    debug!("Detected requirement {} resolved to id {}", req, id);
+    Some(id)
+}
+
+// building missing Erlangs on first use is opt-in, through either the
+// env variable ERLUP_AUTO_INSTALL or `auto_install` in the `[erlup]` section
+fn auto_install(config: &Ini) -> bool {
+    match env::var("ERLUP_AUTO_INSTALL") {
+        Ok(v) => parse_bool(&v).unwrap_or(false),
+        Err(_) => lookup_bool("erlup", "auto_install", config).unwrap_or(false),
+    }
+}
+
+// OTP version of an install, as recorded when it was built or read from the install itself
+pub fn install_version(id: &str, dist_dir: &str, conf: &Ini) -> Option<OtpVersion> {
+    let vsn = lookup_install_option(id, "otp_version", conf)
+        .or_else(|| version::read_otp_version(Path::new(dist_dir)))?;
+    OtpVersion::parse(&vsn)
+}
+
+pub fn highest_matching_install(req: &VersionReq, conf: &Ini) -> Option<(String, String)> {
+    let erlangs = conf.section(Some("erlangs"))?;
+    erlangs
+        .iter()
+        .filter(|(id, _)| lookup_install_option(id, "status", conf).as_deref() != Some("broken"))
+        .filter_map(|(id, dir)| install_version(id, dir, conf).map(|vsn| (vsn, id, dir)))
+        .filter(|(vsn, _, _)| req.matches(vsn))
+        .max_by(|(a, _, _), (b, _, _)| a.cmp(b))
+        .map(|(_, id, dir)| (id.to_string(), dir.to_string()))
+}
+
+/// The installs matching `req` that `highest_matching_install` skips for being broken.
+pub fn broken_matching_installs(req: &VersionReq, conf: &Ini) -> Vec<String> {
+    let erlangs = match conf.section(Some("erlangs")) {
+        Some(erlangs) => erlangs,
+        None => return vec![],
+    };
+    erlangs
+        .iter()
+        .filter(|(id, _)| lookup_install_option(id, "status", conf).as_deref() == Some("broken"))
+        .filter(|(id, dir)| install_version(id, dir, conf).is_some_and(|vsn| req.matches(&vsn)))
+        .map(|(id, _)| id.to_string())
+        .collect()
 }
 
 pub fn read_config(config_file: String) -> Ini {
@@ -113,6 +267,20 @@ pub fn lookup_cache_dir(conf: &Ini) -> &str {
     lookup_or_exit("erlup", "dir", error_message, conf)
 }
 
+/// The on/off settings, like `detect` or `auto_install`, take `true`, `1` or `yes`
+/// and `false`, `0` or `no`.
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Some(true),
+        "false" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn lookup_bool(section: &str, key: &str, conf: &Ini) -> Option<bool> {
+    lookup(section, key.to_string(), conf).and_then(|v| parse_bool(&v))
+}
+
 pub fn lookup(section: &str, key: String, conf: &Ini) -> Option<String> {
     debug!("reading section '{}' key '{}'", section, key);
     match conf.section(Some(section)) {
@@ -121,6 +289,66 @@ pub fn lookup(section: &str, key: String, conf: &Ini) -> Option<String> {
     }
 }
 
+// per repo settings live in a section named after the repo, eg. `[repo.default]`
+pub fn lookup_repo_option(repo: &str, key: &str, conf: &Ini) -> Option<String> {
+    lookup(&format!("repo.{}", repo), key.to_string(), conf)
+}
+
+pub fn update_repo_option(repo: &str, key: &str, value: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some(format!("repo.{}", repo)))
+        .set(key, value);
+    config.write_to_file(config_file).unwrap();
+}
+
+// applications the build must include, from `required_apps` in both the `[erlup]`
+// and the repo's `[repo.<name>]` sections, eg. `required_apps=crypto,ssl,observer`
+pub fn required_apps(repo: &str, conf: &Ini) -> Vec<String> {
+    let mut apps: Vec<String> = vec![
+        lookup("erlup", "required_apps".to_string(), conf),
+        lookup_repo_option(repo, "required_apps", conf),
+    ]
+    .into_iter()
+    .flatten()
+    .flat_map(|apps| {
+        apps.split(',')
+            .map(|app| app.trim().to_string())
+            .filter(|app| !app.is_empty())
+            .collect::<Vec<String>>()
+    })
+    .collect();
+    apps.sort();
+    apps.dedup();
+    apps
+}
+
+// metadata about an install is kept in a section named after its id, eg. `[erlang.OTP-26.2]`
+pub fn lookup_install_option(id: &str, key: &str, conf: &Ini) -> Option<String> {
+    lookup(&format!("erlang.{}", id), key.to_string(), conf)
+}
+
+// installs registered with `erlup link` are used in place, erlup never changes them
+pub fn is_linked(id: &str, conf: &Ini) -> bool {
+    lookup_install_option(id, "linked", conf).as_deref() == Some("true")
+}
+
+pub fn update_install_option(id: &str, key: &str, value: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some(format!("erlang.{}", id)))
+        .set(key, value);
+    config.write_to_file(config_file).unwrap();
+}
+
+pub fn delete_install_option(id: &str, key: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some(format!("erlang.{}", id)))
+        .delete(&key);
+    config.write_to_file(config_file).unwrap();
+}
+
 pub fn lookup_or_exit<'a>(section: &str, key: &str, msg: &str, conf: &'a Ini) -> &'a str {
     debug!("reading section '{}' key '{}'", section, key);
     let section = conf.section(Some(section)).unwrap();
@@ -147,6 +375,43 @@ pub fn lookup_with_default<'a>(
     }
 }
 
+pub fn update_elixir(id: &str, dir: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config.with_section(Some("elixirs".to_owned())).set(id, dir);
+    config.write_to_file(config_file).unwrap();
+}
+
+pub fn delete_elixir(id: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config.with_section(Some("elixirs".to_owned())).delete(&id);
+    config.delete(Some(format!("elixir.{}", id)));
+    if lookup("erlup", "elixir_default".to_string(), &config).as_deref() == Some(id) {
+        config
+            .with_section(Some("erlup".to_owned()))
+            .delete(&"elixir_default");
+    }
+    config.write_to_file(config_file).unwrap();
+}
+
+pub fn lookup_elixir_option(id: &str, key: &str, conf: &Ini) -> Option<String> {
+    lookup(&format!("elixir.{}", id), key.to_string(), conf)
+}
+
+pub fn update_elixir_option(id: &str, key: &str, value: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some(format!("elixir.{}", id)))
+        .set(key, value);
+    config.write_to_file(config_file).unwrap();
+}
+
+pub fn get_elixirs(config: &Ini) -> Vec<(&str, &str)> {
+    match config.section(Some("elixirs")) {
+        Some(section) => section.iter().collect::<Vec<(&str, &str)>>(),
+        None => vec![],
+    }
+}
+
 pub fn update(id: String, dir: &str, config_file: &str) {
     let mut config = Ini::load_from_file(config_file).unwrap();
     config.with_section(Some("erlangs".to_owned())).set(id, dir);
@@ -156,22 +421,89 @@ pub fn update(id: String, dir: &str, config_file: &str) {
 pub fn delete(id: String, config_file: &str) {
     let mut config = Ini::load_from_file(config_file).unwrap();
     config.with_section(Some("erlangs".to_owned())).delete(&id);
+    config.delete(Some(format!("erlang.{}", id)));
+    for alias in aliases_of(&id, &config) {
+        config
+            .with_section(Some("aliases".to_owned()))
+            .delete(&alias);
+    }
+    let resolved: Vec<String> = get_resolved(&config)
+        .iter()
+        .filter(|(_, resolved_id)| *resolved_id == id)
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect();
+    for project_dir in resolved {
+        config
+            .with_section(Some("resolved".to_owned()))
+            .delete(&project_dir);
+    }
+    config.write_to_file(config_file).unwrap();
+}
+
+/// Moves everything in the config referring to the install `old` over to `new`,
+/// which is installed at `dist_dir`.
+pub fn rename_install(old: &str, new: &str, dist_dir: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some("erlangs".to_owned()))
+        .delete(&old)
+        .set(new, dist_dir);
+
+    if let Some(settings) = config.delete(Some(format!("erlang.{}", old))) {
+        for (k, v) in settings.iter() {
+            config
+                .with_section(Some(format!("erlang.{}", new)))
+                .set(k, v);
+        }
+    }
+
+    if lookup("erlup", "default".to_string(), &config).as_deref() == Some(old) {
+        config
+            .with_section(Some("erlup".to_owned()))
+            .set("default", new);
+    }
+
+    for alias in aliases_of(old, &config) {
+        config
+            .with_section(Some("aliases".to_owned()))
+            .set(alias, new);
+    }
+
+    let projects: Vec<String> = get_projects(&config)
+        .iter()
+        .filter(|(_, id)| *id == old)
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect();
+    for project_dir in projects {
+        config
+            .with_section(Some("projects".to_owned()))
+            .set(project_dir, new);
+    }
+    let resolved: Vec<String> = get_resolved(&config)
+        .iter()
+        .filter(|(_, id)| *id == old)
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect();
+    for project_dir in resolved {
+        config
+            .with_section(Some("resolved".to_owned()))
+            .set(project_dir, new);
+    }
+
     config.write_to_file(config_file).unwrap();
 }
 
 pub fn switch(id: &str) {
-    let (_, config) = home_config();
-    match lookup("erlangs", id.to_string(), &config) {
+    let (config_file, config) = home_config();
+    match lookup("erlangs", id.to_string(), &config)
+        .or_else(|| lookup("aliases", id.to_string(), &config))
+    {
         Some(_) => {
-            let cwd_config = Path::new("erlup.config");
-            {
-                let _ = File::create(cwd_config);
-            }
-            let mut mut_config = Ini::load_from_file("erlup.config").unwrap();
-            mut_config
-                .with_section(Some("config".to_owned()))
-                .set("erlang", id);
-            mut_config.write_to_file("erlup.config").unwrap();
+            let cwd = env::current_dir().unwrap_or_else(|e| {
+                error!("unable to get current directory: {}", e);
+                process::exit(1)
+            });
+            set_project_erlang(&cwd, id, &config_file);
             info!("Switched Erlang used in this directory to {}", id);
             info!("Wrote setting to file {}", "./erlup.config");
         }
@@ -182,6 +514,134 @@ pub fn switch(id: &str) {
     }
 }
 
+/// Writes the Elixir to use to the project's erlup.config, next to its Erlang.
+pub fn set_project_elixir(project_dir: &Path, id: &str) {
+    let project_config = project_dir.join("erlup.config");
+    let mut mut_config = Ini::load_from_file(&project_config).unwrap_or_default();
+    mut_config
+        .with_section(Some("config".to_owned()))
+        .set("elixir", id);
+    mut_config.write_to_file(&project_config).unwrap();
+}
+
+/// Writes the Erlang to use to the project's erlup.config, keeping any other
+/// settings in it, and registers the project so commands like `erlup upgrade`
+/// can find it later.
+pub fn set_project_erlang(project_dir: &Path, id: &str, config_file: &str) {
+    let project_config = project_dir.join("erlup.config");
+    let mut mut_config = Ini::load_from_file(&project_config).unwrap_or_default();
+    mut_config
+        .with_section(Some("config".to_owned()))
+        .set("erlang", id);
+    mut_config.write_to_file(&project_config).unwrap();
+
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    config
+        .with_section(Some("projects".to_owned()))
+        .set(project_dir.to_str().unwrap(), id);
+    config.write_to_file(config_file).unwrap();
+}
+
+// projects registered by `erlup switch`, as (project dir, id) pairs
+pub fn get_projects(config: &Ini) -> Vec<(&str, &str)> {
+    match config.section(Some("projects")) {
+        Some(section) => section.iter().collect::<Vec<(&str, &str)>>(),
+        None => vec![],
+    }
+}
+
+// the Erlang a project's erlup.config currently asks for, it may have been
+// changed by hand since `erlup switch` recorded the project
+pub fn project_erlang(project_dir: &str) -> Option<String> {
+    let project_config = Ini::load_from_file(Path::new(project_dir).join("erlup.config")).ok()?;
+    lookup("config", "erlang".to_string(), &project_config)
+}
+
+/// The known projects whose erlup.config picks the install `id`, either by
+/// its id or through a version requirement, and those that last resolved to it.
+pub fn projects_using(id: &str, config: &Ini) -> Vec<String> {
+    let dist_dir = lookup("erlangs", id.to_string(), config);
+    let mut projects: Vec<String> = get_projects(config)
+        .into_iter()
+        .filter(|(project_dir, _)| match project_erlang(project_dir) {
+            Some(erl) => erl == id || (dist_dir.is_some() && lookup_erl(&erl, config) == dist_dir),
+            None => false,
+        })
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect();
+    for project_dir in resolved_to(id, config) {
+        if !projects.contains(&project_dir) {
+            projects.push(project_dir);
+        }
+    }
+    projects
+}
+
+/// The project dirs, still around, that last resolved to the install `id`.
+pub fn resolved_to(id: &str, config: &Ini) -> Vec<String> {
+    get_resolved(config)
+        .into_iter()
+        .filter(|(project_dir, resolved_id)| *resolved_id == id && Path::new(project_dir).is_dir())
+        .map(|(project_dir, _)| project_dir.to_string())
+        .collect()
+}
+
+pub fn get_aliases(config: &Ini) -> Vec<(&str, &str)> {
+    match config.section(Some("aliases")) {
+        Some(section) => section.iter().collect::<Vec<(&str, &str)>>(),
+        None => vec![],
+    }
+}
+
+pub fn aliases_of(id: &str, config: &Ini) -> Vec<String> {
+    get_aliases(config)
+        .iter()
+        .filter(|(_, aliased)| *aliased == id)
+        .map(|(alias, _)| alias.to_string())
+        .collect()
+}
+
+/// Makes `alias` refer to the install `id`, so erlup.config files and the
+/// default can use a name like `26` that outlives the install it points to.
+pub fn alias(alias: &str, id: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    if lookup("erlangs", alias.to_string(), &config).is_some() {
+        error!("{} is already the id of an install", alias);
+        process::exit(1);
+    }
+    if lookup("erlangs", id.to_string(), &config).is_none() {
+        error!("{} is not a configured Erlang install", id);
+        process::exit(1);
+    }
+
+    config
+        .with_section(Some("aliases".to_owned()))
+        .set(alias, id);
+    config.write_to_file(config_file).unwrap();
+    info!("{} now refers to {}", alias, id);
+}
+
+pub fn unalias(alias: &str, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    if lookup("aliases", alias.to_string(), &config).is_none() {
+        error!("{} is not an alias", alias);
+        process::exit(1);
+    }
+
+    config
+        .with_section(Some("aliases".to_owned()))
+        .delete(&alias);
+    config.write_to_file(config_file).unwrap();
+    info!("Removed alias {}", alias);
+}
+
+pub fn get_erlangs(config: &Ini) -> Vec<(&str, &str)> {
+    match config.section(Some("erlangs")) {
+        Some(section) => section.iter().collect::<Vec<(&str, &str)>>(),
+        None => vec![],
+    }
+}
+
 pub fn add_repo(repo_id: &str, repo_url: &str, config_file: &str, mut config: Ini) {
     config
         .with_section(Some("repos".to_owned()))
@@ -203,9 +663,59 @@ pub fn delete_repo(repo_id: &String, config_file: &str, mut config: Ini) {
     config.write_to_file(config_file).unwrap();
 }
 
+pub fn rename_repo(old: &str, new: &str, config_file: &str, mut config: Ini) {
+    if let Some(url) = lookup("repos", old.to_string(), &config) {
+        config
+            .with_section(Some("repos".to_owned()))
+            .delete(&old)
+            .set(new, url);
+    }
+
+    // carry over the repo's own settings
+    if let Some(settings) = config.delete(Some(format!("repo.{}", old))) {
+        for (k, v) in settings.iter() {
+            config.with_section(Some(format!("repo.{}", new))).set(k, v);
+        }
+    }
+
+    // and any installs built from it
+    let ids: Vec<String> = get_erlangs(&config)
+        .iter()
+        .filter(|(id, _)| lookup_install_option(id, "repo", &config).as_deref() == Some(old))
+        .map(|(id, _)| id.to_string())
+        .collect();
+    for id in ids {
+        config
+            .with_section(Some(format!("erlang.{}", id)))
+            .set("repo", new);
+    }
+
+    config.write_to_file(config_file).unwrap();
+}
+
+// sets or, with None, removes the default Erlang without any checks
+pub fn update_default(id: Option<&str>, config_file: &str) {
+    let mut config = Ini::load_from_file(config_file).unwrap();
+    match id {
+        Some(id) => {
+            config
+                .with_section(Some("erlup".to_owned()))
+                .set("default", id);
+        }
+        None => {
+            config
+                .with_section(Some("erlup".to_owned()))
+                .delete(&"default");
+        }
+    }
+    config.write_to_file(config_file).unwrap();
+}
+
 pub fn set_default(id: &str) {
     let (_, mut config) = home_config();
-    match lookup("erlangs", id.to_string(), &config) {
+    match lookup("erlangs", id.to_string(), &config)
+        .or_else(|| lookup("aliases", id.to_string(), &config))
+    {
         Some(_) => {
             config
                 .with_section(Some("erlup".to_owned()))
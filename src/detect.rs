@@ -0,0 +1,383 @@
+use glob::glob;
+use ini::Ini;
+use std::fs::read_to_string;
+use std::path::*;
+use std::process;
+
+use crate::config;
+use crate::version::{OtpVersion, VersionReq};
+
+// the oldest and newest OTP major each Elixir 1.x minor release supports
+pub const ELIXIR_OTP: [(u32, u32, u32); 7] = [
+    (13, 22, 25),
+    (14, 23, 26),
+    (15, 24, 26),
+    (16, 24, 26),
+    (17, 25, 27),
+    (18, 25, 27),
+    (19, 26, 28),
+];
+
+/// An OTP version requirement found in one of the project's files.
+pub struct Detected {
+    pub source: String,
+    pub req: String,
+}
+
+/// Looks through the project in `dir` for the OTP versions it needs, in order of
+/// preference: rebar.config, mix.exs, .tool-versions and then CI configuration.
+pub fn detect(dir: &Path) -> Vec<Detected> {
+    let mut detected = vec![];
+
+    if let Some(req) = read(dir, "rebar.config").and_then(|c| rebar_config(&c)) {
+        detected.push(Detected {
+            source: "rebar.config minimum_otp_vsn".to_string(),
+            req,
+        });
+    }
+
+    // the application resource file can carry minimum_otp_vsn as well
+    if let Ok(paths) = glob(dir.join("src/*.app.src").to_str().unwrap()) {
+        for file in paths.filter_map(Result::ok) {
+            if let Some(req) = read_to_string(&file).ok().and_then(|c| rebar_config(&c)) {
+                let source = file.strip_prefix(dir).unwrap_or(&file);
+                detected.push(Detected {
+                    source: format!("{} minimum_otp_vsn", source.display()),
+                    req,
+                });
+            }
+        }
+    }
+
+    if let Some(contents) = read(dir, "mix.exs").map(|c| strip_comments(&c, '#')) {
+        if let Some(req) = quoted_value(&contents, "erlang:").and_then(|r| elixir_requirement(&r)) {
+            detected.push(Detected {
+                source: "mix.exs erlang requirement".to_string(),
+                req,
+            });
+        }
+        if let Some(req) = quoted_value(&contents, "elixir:").and_then(|r| elixir_min_otp(&r)) {
+            detected.push(Detected {
+                source: "mix.exs elixir requirement".to_string(),
+                req,
+            });
+        }
+    }
+
+    if let Some(req) = read(dir, ".tool-versions").and_then(|c| tool_versions(&c)) {
+        detected.push(Detected {
+            source: ".tool-versions".to_string(),
+            req,
+        });
+    }
+
+    let mut ci_files = vec![dir.join(".travis.yml")];
+    for pattern in [".github/workflows/*.yml", ".github/workflows/*.yaml"] {
+        if let Ok(paths) = glob(dir.join(pattern).to_str().unwrap()) {
+            ci_files.extend(paths.filter_map(Result::ok));
+        }
+    }
+    for file in ci_files {
+        if let Some(req) = read_to_string(&file).ok().and_then(|c| ci_otp_versions(&c)) {
+            let source = file.strip_prefix(dir).unwrap_or(&file);
+            detected.push(Detected {
+                source: source.display().to_string(),
+                req,
+            });
+        }
+    }
+
+    detected
+}
+
+/// `erlup detect`, prints what the project needs and which installed Erlang fits.
+pub fn run(write: bool, config: &Ini) {
+    let detected = detect(Path::new("."));
+    if detected.is_empty() {
+        error!("No OTP version requirement found in rebar.config, src/*.app.src, mix.exs, .tool-versions or CI configuration");
+        process::exit(1);
+    }
+
+    for d in detected.iter() {
+        println!("{}: {}", d.source, d.req);
+    }
+
+    match compatible_install(&detected, config) {
+        Some((id, req)) => {
+            println!("Compatible install: {} (matches {})", id, req);
+            if write {
+                config::switch(&id);
+            }
+        }
+        None => {
+            error!("No installed Erlang matches {}", detected[0].req);
+            let mut broken: Vec<String> = detected
+                .iter()
+                .filter_map(|d| VersionReq::parse(&d.req))
+                .flat_map(|req| config::broken_matching_installs(&req, config))
+                .collect();
+            broken.sort();
+            broken.dedup();
+            if !broken.is_empty() {
+                error!("Skipped broken installs that match: {}", broken.join(", "));
+            }
+            error!("Build one with `erlup build <tag>`, eg. `erlup build latest`");
+            process::exit(1)
+        }
+    }
+}
+
+/// The highest installed Erlang matching the most preferred requirement that
+/// any install satisfies, along with that requirement.
+pub fn compatible_install(detected: &[Detected], config: &Ini) -> Option<(String, String)> {
+    detected.iter().find_map(|d| {
+        let req = VersionReq::parse(&d.req)?;
+        let (id, _) = config::highest_matching_install(&req, config)?;
+        Some((id, d.req.clone()))
+    })
+}
+
+fn read(dir: &Path, file: &str) -> Option<String> {
+    read_to_string(dir.join(file)).ok()
+}
+
+// the first double quoted string after `key`
+fn quoted_value(contents: &str, key: &str) -> Option<String> {
+    let rest = &contents[contents.find(key)? + key.len()..];
+    let start = rest.find('"')? + 1;
+    let end = start + rest[start..].find('"')?;
+    Some(rest[start..end].to_string())
+}
+
+// drops everything after the comment character on each line, unless it's in a string
+fn strip_comments(contents: &str, comment: char) -> String {
+    contents
+        .lines()
+        .map(|line| {
+            let mut in_string = false;
+            let end = line
+                .char_indices()
+                .find(|(_, c)| {
+                    if *c == '"' {
+                        in_string = !in_string;
+                    }
+                    *c == comment && !in_string
+                })
+                .map(|(i, _)| i)
+                .unwrap_or(line.len());
+            &line[..end]
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// {minimum_otp_vsn, "25.3"}. in rebar.config or an .app.src
+fn rebar_config(contents: &str) -> Option<String> {
+    let contents = strip_comments(contents, '%');
+    let vsn = quoted_value(&contents, "minimum_otp_vsn")?;
+    OtpVersion::parse(&vsn)?;
+    Some(format!(">= {}", vsn))
+}
+
+// Elixir style requirements, eg. "~> 26.1" is >= 26.1 and < 27
+fn elixir_requirement(req: &str) -> Option<String> {
+    let req = req.trim();
+    let req = match req.strip_prefix("~>") {
+        Some(vsn) => {
+            let vsn = vsn.trim();
+            let parts: Vec<u32> = vsn
+                .split('.')
+                .map(|p| p.parse::<u32>().ok())
+                .collect::<Option<Vec<u32>>>()?;
+            match parts.as_slice() {
+                [major] => format!(">= {}, < {}", major, major + 1),
+                [major, ..] => format!(">= {}, < {}", vsn, major + 1),
+                [] => return None,
+            }
+        }
+        None => req.to_string(),
+    };
+    VersionReq::parse(&req)?;
+    Some(req)
+}
+
+// the OTP an Elixir requirement like "~> 1.15" needs
+fn elixir_min_otp(req: &str) -> Option<String> {
+    let vsn = req.trim_start_matches(|c: char| !c.is_ascii_digit());
+    let mut parts = vsn.split('.');
+    let (major, minor) = (parts.next()?, parts.next()?);
+    if major != "1" {
+        return None;
+    }
+    let minor = minor
+        .trim_end_matches(|c: char| !c.is_ascii_digit())
+        .parse::<u32>()
+        .ok()?;
+    let (_, otp, _) = ELIXIR_OTP
+        .iter()
+        .rev()
+        .find(|(elixir, _, _)| *elixir <= minor)?;
+    Some(format!(">= {}", otp))
+}
+
+// erlang 26.2.1
+fn tool_versions(contents: &str) -> Option<String> {
+    contents.lines().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("erlang"), Some(vsn)) => OtpVersion::parse(vsn).map(|_| vsn.to_string()),
+            _ => None,
+        }
+    })
+}
+
+// the OTP versions a CI configuration tests against, eg. `otp-version: 26.2` or
+// `otp: ['25.3', '26']` for GitHub Actions and `otp_release:` lists for Travis
+fn ci_otp_versions(contents: &str) -> Option<String> {
+    let keys = ["otp-version:", "otp_version:", "otp_release:", "otp:"];
+    let mut versions: Vec<OtpVersion> = vec![];
+    let mut in_list = false;
+
+    for line in contents.lines() {
+        let trimmed = line.trim().trim_start_matches("- ");
+        let value = match keys.iter().find_map(|k| trimmed.strip_prefix(k)) {
+            Some(value) => {
+                in_list = value.trim().is_empty();
+                value
+            }
+            None if in_list && line.trim().starts_with('-') => trimmed,
+            None => {
+                in_list = false;
+                continue;
+            }
+        };
+
+        versions.extend(
+            value
+                .split([',', '[', ']'])
+                .map(|v| v.trim().trim_matches(|c| c == '\'' || c == '"'))
+                .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+                .filter_map(OtpVersion::parse),
+        );
+    }
+
+    let min = versions.iter().min()?;
+    let max = versions.iter().max()?;
+    Some(format!(">= {}, < {}", min, max.major() + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+    use tempdir::TempDir;
+
+    #[test]
+    fn elixir_otp_table_is_ordered_and_consistent() {
+        for window in ELIXIR_OTP.windows(2) {
+            assert_eq!(window[0].0 + 1, window[1].0);
+            assert!(window[0].1 <= window[1].1);
+            assert!(window[0].2 <= window[1].2);
+        }
+        for (_, min, max) in ELIXIR_OTP.iter() {
+            assert!(min <= max);
+        }
+    }
+
+    #[test]
+    fn strips_comments_outside_strings() {
+        assert_eq!(
+            strip_comments("{minimum_otp_vsn, \"25\"}. % was \"24\"", '%'),
+            "{minimum_otp_vsn, \"25\"}. "
+        );
+        assert_eq!(strip_comments("\"a#b\" # c\n# d", '#'), "\"a#b\" \n");
+    }
+
+    #[test]
+    fn finds_quoted_values() {
+        assert_eq!(
+            quoted_value("[erlang: \"~> 26.1\", elixir: \"~> 1.15\"]", "elixir:").as_deref(),
+            Some("~> 1.15")
+        );
+        assert_eq!(quoted_value("elixir: \"~> 1.15", "elixir:"), None);
+        assert_eq!(quoted_value("erlang: \"26\"", "elixir:"), None);
+    }
+
+    #[test]
+    fn parses_rebar_config() {
+        assert_eq!(
+            rebar_config("{erl_opts, []}.\n{minimum_otp_vsn, \"25.3\"}.").as_deref(),
+            Some(">= 25.3")
+        );
+        assert_eq!(rebar_config("%% {minimum_otp_vsn, \"25.3\"}."), None);
+        assert_eq!(rebar_config("{minimum_otp_vsn, \"any\"}."), None);
+        assert_eq!(rebar_config("{erl_opts, []}."), None);
+    }
+
+    #[test]
+    fn parses_mix_exs_requirements() {
+        assert_eq!(elixir_requirement("~> 26").as_deref(), Some(">= 26, < 27"));
+        assert_eq!(
+            elixir_requirement("~> 26.1").as_deref(),
+            Some(">= 26.1, < 27")
+        );
+        assert_eq!(elixir_requirement(">= 25.3").as_deref(), Some(">= 25.3"));
+        assert_eq!(elixir_requirement("~> x"), None);
+        assert_eq!(elixir_min_otp("~> 1.15").as_deref(), Some(">= 24"));
+        assert_eq!(elixir_min_otp("~> 1.99.0").as_deref(), Some(">= 26"));
+        assert_eq!(elixir_min_otp("~> 1.12"), None);
+        assert_eq!(elixir_min_otp("~> 2.0"), None);
+    }
+
+    #[test]
+    fn parses_tool_versions() {
+        assert_eq!(
+            tool_versions("elixir 1.16.2-otp-26\nerlang 26.2.1\n").as_deref(),
+            Some("26.2.1")
+        );
+        assert_eq!(tool_versions("elixir 1.16.2-otp-26\n"), None);
+        assert_eq!(tool_versions("erlang ref:master\n"), None);
+    }
+
+    #[test]
+    fn parses_ci_otp_versions() {
+        let actions = "    strategy:\n      matrix:\n        otp: ['25.3', '26']\n";
+        assert_eq!(ci_otp_versions(actions).as_deref(), Some(">= 25.3, < 27"));
+        let travis = "otp_release:\n  - 24.3\n  - 26.1\nscript: make\n  - 99\n";
+        assert_eq!(ci_otp_versions(travis).as_deref(), Some(">= 24.3, < 27"));
+        assert_eq!(ci_otp_versions("otp-version: ${{ matrix.otp }}\n"), None);
+    }
+
+    #[test]
+    fn detects_in_order_of_preference() {
+        let dir = TempDir::new("erlup_detect").unwrap();
+        write(dir.path().join(".tool-versions"), "erlang 26.2.1\n").unwrap();
+        write(
+            dir.path().join("rebar.config"),
+            "{minimum_otp_vsn, \"25\"}.\n",
+        )
+        .unwrap();
+        write(dir.path().join("mix.exs"), "# erlang: \"~> 24\"\n").unwrap();
+        let detected = detect(dir.path());
+        let reqs: Vec<&str> = detected.iter().map(|d| d.req.as_str()).collect();
+        assert_eq!(reqs, [">= 25", "26.2.1"]);
+    }
+
+    #[test]
+    fn reads_the_erlang_of_erlup_config() {
+        let dir = TempDir::new("erlup_detect").unwrap();
+        let project = dir.path().to_str().unwrap();
+        assert_eq!(config::project_erlang(project), None);
+        write(dir.path().join("erlup.config"), "[config]\nelixir=1.16.2\n").unwrap();
+        assert_eq!(config::project_erlang(project), None);
+        write(
+            dir.path().join("erlup.config"),
+            "[config]\nerlang=>= 25.3, < 27\n",
+        )
+        .unwrap();
+        let erlang = config::project_erlang(project).unwrap();
+        assert!(VersionReq::parse(&erlang)
+            .unwrap()
+            .matches(&OtpVersion::parse("26.2").unwrap()));
+    }
+}
@@ -0,0 +1,243 @@
+use console::style;
+use glob::glob;
+use ini::Ini;
+use std::env;
+use std::path::*;
+use std::process;
+
+use crate::build::{self, CHECKMARK, FAIL, WARNING};
+use crate::config;
+
+// common places for headers and libraries across Linux distros, Homebrew and MacPorts
+const INCLUDE_DIRS: [&str; 6] = [
+    "/usr/include",
+    "/usr/local/include",
+    "/opt/homebrew/include",
+    "/opt/homebrew/opt/openssl/include",
+    "/usr/local/opt/openssl/include",
+    "/opt/local/include",
+];
+
+const LIB_DIRS: [&str; 10] = [
+    "/lib",
+    "/lib64",
+    "/usr/lib",
+    "/usr/lib64",
+    "/usr/lib/*-linux-gnu",
+    "/usr/local/lib",
+    "/opt/homebrew/lib",
+    "/opt/homebrew/opt/openssl/lib",
+    "/usr/local/opt/openssl/lib",
+    "/opt/local/lib",
+];
+
+enum Status {
+    Pass(String),
+    Warn(String),
+    Fail(String),
+}
+
+struct Check {
+    name: &'static str,
+    status: Status,
+    hint: &'static str,
+}
+
+pub fn run(bin_path: &Path, config: &Ini) {
+    let dir = config::lookup_cache_dir(config);
+    let links_dir = Path::new(dir).join("bin");
+
+    let checks = vec![
+        required_tool("git", &["git"], "install git with your system package manager"),
+        required_tool(
+            "make",
+            &["make", "gmake"],
+            "install make, eg. `build-essential` on Debian/Ubuntu or the Xcode command line tools on macOS",
+        ),
+        c_compiler(),
+        optional_tool(
+            "autoconf",
+            &["autoconf"],
+            "install autoconf, it is needed to generate ./configure when the checked out source doesn't include it",
+        ),
+        required_tool("perl", &["perl"], "install perl with your system package manager"),
+        headers(
+            "ncurses/termcap headers",
+            &["ncurses.h", "curses.h", "term.h", "termcap.h"],
+            true,
+            "install the ncurses development package, eg. `libncurses-dev` or `ncurses-devel`, or build with --configure-opt=--without-termcap",
+        ),
+        headers(
+            "OpenSSL headers",
+            &["openssl/ssl.h"],
+            false,
+            "install the OpenSSL development package, eg. `libssl-dev` or `openssl-devel`, or point to one with --with-ssl, without it crypto and ssl won't be built",
+        ),
+        libraries(
+            "OpenSSL libraries",
+            "libcrypto",
+            "install OpenSSL or point to one with --with-ssl, without it crypto and ssl won't be built",
+        ),
+        optional_tool(
+            "javac",
+            &["javac"],
+            "install a JDK to build jinterface, or ignore if it isn't needed",
+        ),
+        optional_tool(
+            "wxWidgets",
+            &["wx-config"],
+            "install the wxWidgets development package to build wx, observer and debugger",
+        ),
+        headers(
+            "unixODBC headers",
+            &["sql.h"],
+            false,
+            "install the unixODBC development package to build odbc, or ignore if it isn't needed",
+        ),
+        links_on_path(&links_dir),
+        links_point_to(&links_dir, bin_path, config),
+    ];
+
+    let mut failed = false;
+    for check in checks.iter() {
+        match &check.status {
+            Status::Pass(detail) => {
+                println!(" {} {}: {}", CHECKMARK, check.name, detail);
+            }
+            Status::Warn(detail) => {
+                println!(" {} {}: {}", WARNING, check.name, detail);
+                println!("    {}", style(check.hint).dim());
+            }
+            Status::Fail(detail) => {
+                failed = true;
+                println!(" {} {}: {}", FAIL, check.name, detail);
+                println!("    {}", style(check.hint).dim());
+            }
+        }
+    }
+
+    if failed {
+        println!(
+            "{}",
+            style("Missing prerequisites for building Erlang")
+                .red()
+                .bold()
+        );
+        process::exit(1);
+    }
+}
+
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+fn find_any(names: &[&str]) -> Option<PathBuf> {
+    names.iter().find_map(|name| find_executable(name))
+}
+
+fn required_tool(name: &'static str, names: &[&str], hint: &'static str) -> Check {
+    let status = match find_any(names) {
+        Some(path) => Status::Pass(path.display().to_string()),
+        None => Status::Fail("not found in PATH".to_string()),
+    };
+    Check { name, status, hint }
+}
+
+fn optional_tool(name: &'static str, names: &[&str], hint: &'static str) -> Check {
+    let status = match find_any(names) {
+        Some(path) => Status::Pass(path.display().to_string()),
+        None => Status::Warn("not found in PATH".to_string()),
+    };
+    Check { name, status, hint }
+}
+
+fn c_compiler() -> Check {
+    // configure respects CC so check that first
+    let mut names = vec![];
+    let cc = env::var("CC").unwrap_or_default();
+    if let Some(cc) = shell_words::split(&cc)
+        .ok()
+        .and_then(|words| words.first().cloned())
+    {
+        names.push(cc);
+    }
+    names.extend(["cc", "gcc", "clang"].iter().map(|s| s.to_string()));
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+
+    required_tool(
+        "C compiler",
+        &names,
+        "install gcc or clang, eg. `build-essential` on Debian/Ubuntu or the Xcode command line tools on macOS",
+    )
+}
+
+fn headers(name: &'static str, files: &[&str], required: bool, hint: &'static str) -> Check {
+    let found = INCLUDE_DIRS
+        .iter()
+        .flat_map(|dir| files.iter().map(move |file| Path::new(dir).join(file)))
+        .find(|header| header.is_file());
+    let status = match (found, required) {
+        (Some(header), _) => Status::Pass(header.display().to_string()),
+        (None, true) => Status::Fail("not found".to_string()),
+        (None, false) => Status::Warn("not found".to_string()),
+    };
+    Check { name, status, hint }
+}
+
+fn libraries(name: &'static str, lib: &str, hint: &'static str) -> Check {
+    let found = LIB_DIRS
+        .iter()
+        .filter_map(|dir| glob(&format!("{}/{}.*", dir, lib)).ok())
+        .flat_map(|paths| paths.filter_map(Result::ok))
+        .next();
+    let status = match found {
+        Some(path) => Status::Pass(path.display().to_string()),
+        None => Status::Warn("not found".to_string()),
+    };
+    Check { name, status, hint }
+}
+
+fn links_on_path(links_dir: &Path) -> Check {
+    let on_path = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir == links_dir))
+        .unwrap_or(false);
+    let status = if on_path {
+        Status::Pass(links_dir.display().to_string())
+    } else {
+        Status::Fail(format!("{} is not in PATH", links_dir.display()))
+    };
+    Check {
+        name: "erlup bin dir in PATH",
+        status,
+        hint: "add it to PATH in your shell profile, eg. `export PATH=~/.cache/erlup/bin:$PATH`",
+    }
+}
+
+fn links_point_to(links_dir: &Path, bin_path: &Path, config: &Ini) -> Check {
+    let broken: Vec<String> = build::linked_bins(config)
+        .iter()
+        .map(|b| Path::new(b).file_name().unwrap())
+        .filter(|f| match std::fs::read_link(links_dir.join(f)) {
+            Ok(target) => target != bin_path,
+            Err(_) => true,
+        })
+        .map(|f| f.to_string_lossy().to_string())
+        .collect();
+    let status = if broken.is_empty() {
+        Status::Pass(format!("all link to {}", bin_path.display()))
+    } else {
+        Status::Warn(format!(
+            "not linked to {}: {}",
+            bin_path.display(),
+            broken.join(", ")
+        ))
+    };
+    Check {
+        name: "erlup links",
+        status,
+        hint: "run `erlup update-links` to point them at this erlup binary",
+    }
+}
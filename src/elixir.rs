@@ -0,0 +1,369 @@
+use console::style;
+use indicatif::HumanDuration;
+use ini::Ini;
+use std::env;
+use std::env::Args;
+use std::fs::*;
+use std::os::unix::prelude::CommandExt;
+use std::path::*;
+use std::process;
+use std::process::Command;
+use std::time::Instant;
+
+use crate::build::{self, BuildDir, BuildResult, CHECKMARK, WARNING};
+use crate::config;
+use crate::detect::ELIXIR_OTP;
+use crate::version::{self, OtpVersion, VersionReq};
+
+pub const BINS: [&str; 4] = ["bin/elixir", "bin/elixirc", "bin/iex", "bin/mix"];
+
+enum Compatibility {
+    Supported,
+    Untested(String),
+    Unsupported(String),
+}
+
+// Elixir refuses to compile on an OTP older than it supports, a newer OTP
+// may work but isn't supported yet
+fn compatibility(elixir_vsn: &str, otp: &OtpVersion) -> Compatibility {
+    let minor = match elixir_vsn
+        .trim_start_matches('v')
+        .strip_prefix("1.")
+        .and_then(|rest| rest.split('.').next())
+        .and_then(|minor| minor.parse::<u32>().ok())
+    {
+        Some(minor) => minor,
+        None => return Compatibility::Supported,
+    };
+    let (known, min, max) = match ELIXIR_OTP.iter().rev().find(|(m, _, _)| *m <= minor) {
+        Some(range) => *range,
+        None => return Compatibility::Supported,
+    };
+
+    if otp.major() < min {
+        Compatibility::Unsupported(format!(
+            "Elixir {} needs OTP {} or newer, {} is OTP {}",
+            elixir_vsn,
+            min,
+            otp,
+            otp.major()
+        ))
+    } else if known == minor && otp.major() > max {
+        Compatibility::Untested(format!(
+            "Elixir {} supports OTP up to {}, {} is OTP {}",
+            elixir_vsn,
+            max,
+            otp,
+            otp.major()
+        ))
+    } else {
+        Compatibility::Supported
+    }
+}
+
+fn otp_version(erl_dir: &str) -> Option<OtpVersion> {
+    version::read_otp_version(Path::new(erl_dir)).and_then(|vsn| OtpVersion::parse(&vsn))
+}
+
+fn read_version(repo_dir: &Path, commit: &str) -> Option<String> {
+    let output = Command::new("git")
+        .args(["show", &format!("{}:VERSION", commit)])
+        .current_dir(repo_dir)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Builds Elixir from `git_ref` of the Elixir repo `repo` with the Erlang
+/// `erlang`, or the one in use here when not given, and installs it to
+/// `elixirs/<id>`.
+#[allow(clippy::too_many_arguments)]
+pub fn build(
+    bin_path: &Path,
+    git_ref: &str,
+    id: Option<String>,
+    repo: &str,
+    erlang: Option<&str>,
+    force: bool,
+    config_file: &str,
+    config: &Ini,
+) {
+    let dir = config::lookup_cache_dir(config);
+    let repo_url = config::lookup("repos", repo.to_string(), config).unwrap_or_else(|| {
+        error!("Repo {} not found in config", repo);
+        error!(
+            "To add it: erlup repo add {} https://github.com/elixir-lang/elixir",
+            repo
+        );
+        process::exit(1)
+    });
+    let repo_dir = Path::new(dir).join("repos").join(repo);
+    if !repo_dir.is_dir() {
+        info!("Cloning repo {} to {}", repo_url, repo_dir.display());
+        build::clone_repo(&repo_url, repo_dir.to_owned(), config);
+    }
+
+    let resolved = build::resolve_ref(&repo_dir, git_ref);
+    info!("Resolved {} to commit {}", git_ref, resolved.commit);
+    let id = id.unwrap_or_else(|| build::default_id(git_ref, &resolved, false));
+
+    let install_dir = Path::new(dir).join("elixirs").join(&id);
+    if install_dir.exists() && !force {
+        error!("Directory for {} already exists: {:?}", id, install_dir);
+        error!("If this is incorrect remove that directory,");
+        error!("provide a different id with --id <id> or provide --force.");
+        process::exit(1);
+    }
+
+    let erl_dir = match erlang {
+        Some(erlang) => config::lookup_erl(erlang, config).unwrap_or_else(|| {
+            error!("{} is not a configured Erlang install", erlang);
+            process::exit(1)
+        }),
+        None => config::erl_to_use(),
+    };
+    debug!("building Elixir {} with Erlang at {}", id, erl_dir);
+
+    // checked before anything is built, Elixir's VERSION is read from the commit
+    let elixir_vsn = read_version(&repo_dir, &resolved.commit).unwrap_or_else(|| {
+        error!(
+            "{} has no VERSION file, is {} an Elixir repo?",
+            git_ref, repo
+        );
+        process::exit(1)
+    });
+    let otp = otp_version(&erl_dir);
+    if let Some(otp) = otp.as_ref() {
+        match compatibility(&elixir_vsn, otp) {
+            Compatibility::Supported => (),
+            Compatibility::Untested(warning) => info!("{} {}", WARNING, warning),
+            Compatibility::Unsupported(failure) => {
+                error!("{}", failure);
+                error!("Pick a compatible Erlang with --erlang <id>");
+                process::exit(1);
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let pb = build::spinner();
+    let build_dir = build::temp_build_dir(&id, config);
+    let src_dir = build_dir.path().to_path_buf();
+
+    pb.set_message(format!("Checking out {}", git_ref));
+    build::checkout(&src_dir, &repo_dir, &resolved.commit, &pb);
+    pb.println(format!(
+        " {} Checking out {} (done in {})",
+        CHECKMARK,
+        git_ref,
+        HumanDuration(started.elapsed())
+    ));
+
+    build::put_erlang_on_path(&erl_dir);
+    // with --force the new install is made next to the old one, which is only
+    // replaced once the new one is complete
+    let staged = install_dir.exists();
+    let prefix_dir = if staged {
+        Path::new(dir).join("elixirs").join(format!("{}.new", id))
+    } else {
+        install_dir.clone()
+    };
+    if staged {
+        // left behind by an earlier --force build that was killed
+        let _ = remove_dir_all(&prefix_dir);
+    }
+    let dist_dir = install_dir.join("dist");
+    let steps = [
+        ("make", vec![]),
+        (
+            "make",
+            vec![
+                "install".to_string(),
+                format!("PREFIX={}", prefix_dir.join("dist").display()),
+            ],
+        ),
+    ];
+    for (command, args) in steps.iter() {
+        let failed = match build::exec(command, args, &src_dir, Instant::now(), &pb) {
+            Ok(BuildResult::Success) => false,
+            Ok(BuildResult::Fail) => true,
+            Err(e) => {
+                error!("build failed: {}", e);
+                true
+            }
+        };
+        if failed {
+            if staged {
+                let _ = remove_dir_all(&prefix_dir);
+            }
+            build::abort_build(build_dir, &pb)
+        }
+    }
+    if let BuildDir::Temp(dir) = build_dir {
+        drop(dir);
+    }
+
+    // Elixir's bin links are relative, so the staged install works after the move
+    if staged {
+        let _ = remove_dir_all(&install_dir);
+        rename(&prefix_dir, &install_dir).unwrap_or_else(|e| {
+            error!(
+                "unable to move {} to {}: {}",
+                prefix_dir.display(),
+                install_dir.display(),
+                e
+            );
+            config::delete_elixir(&id, config_file);
+            process::exit(1)
+        });
+    }
+
+    let links_dir = Path::new(dir).join("bin");
+    build::link_bins(&BINS, bin_path, &links_dir);
+
+    config::update_elixir(&id, dist_dir.to_str().unwrap(), config_file);
+    config::update_elixir_option(&id, "repo", repo, config_file);
+    config::update_elixir_option(&id, "ref", git_ref, config_file);
+    config::update_elixir_option(&id, "commit", &resolved.commit, config_file);
+    config::update_elixir_option(&id, "elixir_version", &elixir_vsn, config_file);
+    if let Some(otp) = otp {
+        config::update_elixir_option(&id, "otp_version", &otp.to_string(), config_file);
+    }
+
+    pb.finish_and_clear();
+    println!(
+        "{} Elixir {} build in {}",
+        style("Finished").green().bold(),
+        elixir_vsn,
+        HumanDuration(started.elapsed())
+    );
+}
+
+/// The Elixir install an id or version requirement like `1.16` refers to, the
+/// highest matching one for a requirement.
+fn lookup_elixir(elixir: &str, config: &Ini) -> Option<(String, String)> {
+    if let Some(dir) = config::lookup("elixirs", elixir.to_string(), config) {
+        return Some((elixir.to_string(), dir));
+    }
+
+    let req = VersionReq::parse(elixir.trim_start_matches('v'))?;
+    config::get_elixirs(config)
+        .into_iter()
+        .filter_map(|(id, dir)| {
+            let vsn = config::lookup_elixir_option(id, "elixir_version", config)?;
+            OtpVersion::parse_elixir(&vsn).map(|vsn| (vsn, id, dir))
+        })
+        .filter(|(vsn, _, _)| req.matches(vsn))
+        .max_by(|(a, _, _), (b, _, _)| a.cmp(b))
+        .map(|(_, id, dir)| (id.to_string(), dir.to_string()))
+}
+
+// the `elixir` of ./erlup.config or else the `elixir_default` of `[erlup]`
+fn elixir_to_use(config: &Ini) -> (String, String) {
+    let elixir = Ini::load_from_file("erlup.config")
+        .ok()
+        .and_then(|project| config::lookup("config", "elixir".to_string(), &project))
+        .or_else(|| config::lookup("erlup", "elixir_default".to_string(), config))
+        .unwrap_or_else(|| {
+            error!("No Elixir set. Use `erlup elixir switch <id>` or `erlup elixir default <id>`");
+            process::exit(1)
+        });
+
+    debug!("Using Elixir {}", elixir);
+    lookup_elixir(&elixir, config).unwrap_or_else(|| {
+        error!("No Elixir install found for {}", elixir);
+        process::exit(1)
+    })
+}
+
+/// Runs one of Elixir's executables from the Elixir in use, with the Erlang in
+/// use first in PATH.
+pub fn run(bin: &str, args: Args) {
+    let (_, config) = config::home_config();
+    let (id, elixir_dir) = elixir_to_use(&config);
+    let erl_dir = config::erl_to_use();
+
+    let elixir_vsn = config::lookup_elixir_option(&id, "elixir_version", &config);
+    if let (Some(elixir_vsn), Some(otp)) = (elixir_vsn, otp_version(&erl_dir)) {
+        match compatibility(&elixir_vsn, &otp) {
+            Compatibility::Supported => (),
+            Compatibility::Untested(warning) | Compatibility::Unsupported(warning) => {
+                error!("{}", warning)
+            }
+        }
+    }
+
+    build::put_erlang_on_path(&erl_dir);
+    let cmd = Path::new(&elixir_dir).join("bin").join(bin);
+    debug!("running {}", cmd.display());
+
+    let _ = Command::new(cmd).args(args).exec();
+}
+
+pub fn list(config: &Ini) {
+    let elixirs = config::get_elixirs(config);
+    if elixirs.is_empty() {
+        println!("No Elixir releases installed.");
+    }
+    for (id, dir) in elixirs {
+        match config::lookup_elixir_option(id, "otp_version", config) {
+            Some(otp) => println!("{} -> {} (built with OTP {})", id, dir, otp),
+            None => println!("{} -> {}", id, dir),
+        }
+    }
+}
+
+fn lookup_or_exit(id: &str, config: &Ini) -> String {
+    config::lookup("elixirs", id.to_string(), config).unwrap_or_else(|| {
+        error!("{} is not a configured Elixir install", id);
+        process::exit(1)
+    })
+}
+
+pub fn switch(id: &str, config: &Ini) {
+    lookup_or_exit(id, config);
+    let cwd = env::current_dir().unwrap_or_else(|e| {
+        error!("unable to get current directory: {}", e);
+        process::exit(1)
+    });
+    config::set_project_elixir(&cwd, id);
+    info!("Switched Elixir used in this directory to {}", id);
+    info!("Wrote setting to file {}", "./erlup.config");
+}
+
+pub fn set_default(id: &str, config_file: &str, config: &Ini) {
+    lookup_or_exit(id, config);
+    let mut config = config.clone();
+    config
+        .with_section(Some("erlup".to_owned()))
+        .set("elixir_default", id);
+    config.write_to_file(config_file).unwrap();
+    info!("Default Elixir now {}", id);
+}
+
+pub fn delete(id: &str, config_file: &str, config: &Ini) {
+    let dist_dir = lookup_or_exit(id, config);
+
+    // the install before its config entry, like Erlang installs
+    if let Some(install_dir) = Path::new(&dist_dir).parent() {
+        if install_dir.exists() {
+            remove_dir_all(install_dir).unwrap_or_else(|e| {
+                error!("unable to delete {} due to {}", install_dir.display(), e);
+                process::exit(1)
+            });
+        }
+    }
+    config::delete_elixir(id, config_file);
+    info!("Deleted Elixir {}", id);
+
+    // without an Elixir left the shims would only shadow one installed otherwise
+    if config::get_elixirs(&config::read_config(config_file.to_string())).is_empty() {
+        let links_dir = Path::new(config::lookup_cache_dir(config)).join("bin");
+        for b in BINS.iter() {
+            let _ = remove_file(links_dir.join(Path::new(b).file_name().unwrap()));
+        }
+    }
+}
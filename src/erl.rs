@@ -1,16 +1,47 @@
-use std::path::*;
+use std::env;
 use std::env::Args;
-use std::process::Command;
 use std::os::unix::prelude::CommandExt;
+use std::path::*;
+use std::process;
+use std::process::Command;
 
+use crate::build;
 use crate::config;
 
 pub fn run(bin: &str, args: Args) {
     // no -c argument available in this case
     let erl_dir = config::erl_to_use();
-    let cmd = Path::new(&erl_dir).join("bin").join(bin);
+    let mut cmd = Path::new(&erl_dir).join("bin").join(bin);
+
+    if bin == "rebar3" {
+        // rebar3 is an escript, run by the first escript in PATH
+        build::put_erlang_on_path(&erl_dir);
+        // an install without its own rebar3 uses the one installed otherwise
+        if !cmd.exists() {
+            if let Some(other) = next_in_path(bin) {
+                cmd = other;
+            }
+        }
+    }
 
     debug!("running {}", cmd.to_str().unwrap());
 
-    let _ = Command::new(cmd.to_str().unwrap()).args(args).exec();
+    let err = Command::new(cmd.to_str().unwrap()).args(args).exec();
+
+    // only returns when the command couldn't be run, eg. rebar3 isn't installed
+    error!("unable to run {}: {}", cmd.to_str().unwrap(), err);
+    if bin == "rebar3" {
+        error!("Install it for this Erlang with `erlup rebar3 install`");
+    }
+    process::exit(1)
+}
+
+// the first `bin` in PATH that isn't an erlup shim
+fn next_in_path(bin: &str) -> Option<PathBuf> {
+    let erlup = env::current_exe().ok()?;
+    let path = env::var_os("PATH")?;
+    env::split_paths(&path)
+        .map(|dir| dir.join(bin))
+        .filter(|cmd| cmd.is_file())
+        .find(|cmd| cmd.canonicalize().ok().as_ref() != Some(&erlup))
 }
@@ -0,0 +1,181 @@
+use indicatif::HumanBytes;
+use ini::Ini;
+use std::collections::HashSet;
+use std::fs::*;
+use std::io::{stdin, stdout, Write};
+use std::path::*;
+use std::process;
+
+use crate::build;
+use crate::config;
+use crate::repo;
+
+// every directory directly under `dir` with its size, largest first
+fn sized_dirs(dir: &Path) -> Vec<(String, u64)> {
+    let mut sizes: Vec<(String, u64)> = match read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                (name, repo::dir_size(&entry.path()))
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    sizes.sort_by(|(_, a), (_, b)| b.cmp(a));
+    sizes
+}
+
+/// `erlup du`, the disk usage of installs, repo clones and build trees.
+pub fn du(config: &Ini) {
+    let dir = Path::new(config::lookup_cache_dir(config));
+    let sections = [
+        ("Installs", dir.join("otps")),
+        ("Elixir installs", dir.join("elixirs")),
+        ("Repos", dir.join("repos")),
+        ("Incremental build trees", dir.join("worktrees")),
+        ("Kept build trees", build::builds_dir(config)),
+    ];
+
+    let mut total = 0;
+    for (title, dir) in sections.iter() {
+        let sizes = sized_dirs(dir);
+        if sizes.is_empty() {
+            continue;
+        }
+        println!("{}:", title);
+        for (name, size) in sizes.iter() {
+            println!("  {:>10}  {}", HumanBytes(*size).to_string(), name);
+            total += size;
+        }
+    }
+
+    let store = repo::shared_store_dir(config);
+    if store.exists() {
+        let size = repo::dir_size(&store);
+        println!("Shared object store:");
+        println!(
+            "  {:>10}  {}",
+            HumanBytes(size).to_string(),
+            store.display()
+        );
+        total += size;
+    }
+
+    println!("Total: {}", HumanBytes(total));
+}
+
+/// `erlup gc`, removes install dirs that aren't in the config and config entries
+/// whose dirs are gone, then offers to remove installs nothing refers to.
+pub fn gc(config_file: &str, config: Ini) {
+    let otps_dir = Path::new(config::lookup_cache_dir(&config)).join("otps");
+    let erlangs: Vec<(String, String)> = config::get_erlangs(&config)
+        .iter()
+        .map(|(id, dir)| (id.to_string(), dir.to_string()))
+        .collect();
+
+    let mut cleaned = false;
+    if let Ok(entries) = read_dir(&otps_dir) {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            // linked installs only keep their links in otps/<id>
+            if erlangs
+                .iter()
+                .any(|(id, dir)| Path::new(dir).starts_with(&path) || path == otps_dir.join(id))
+            {
+                continue;
+            }
+            // a build only adds its config entry once it's done
+            let name = entry.file_name().to_string_lossy().to_string();
+            if build::build_in_progress(&name, &config) {
+                info!("Skipping {}, it's being built", path.display());
+                continue;
+            }
+            let question = format!(
+                "{} ({}) isn't a configured install, delete it?",
+                path.display(),
+                HumanBytes(repo::dir_size(&path))
+            );
+            if !confirm(&question) {
+                continue;
+            }
+            remove_dir_all(&path).unwrap_or_else(|e| {
+                error!("unable to delete {} due to {}", path.display(), e);
+                process::exit(1)
+            });
+            info!("Deleted {}", path.display());
+            cleaned = true;
+        }
+    }
+
+    for (id, dir) in erlangs.iter() {
+        if !Path::new(dir).exists() {
+            config::delete(id.to_string(), config_file);
+            info!(
+                "Removed {} from config, its directory {} is missing",
+                id, dir
+            );
+            cleaned = true;
+        }
+    }
+
+    let referenced = referenced_installs(&config);
+    for (id, dir) in erlangs.iter() {
+        // erlup didn't build linked installs and wouldn't free their space
+        if referenced.contains(dir) || !Path::new(dir).exists() || config::is_linked(id, &config) {
+            continue;
+        }
+        let question = format!(
+            "{} ({}) isn't the default or used by a known project, delete it?",
+            id,
+            HumanBytes(repo::dir_size(&build::install_dir(id, &config)))
+        );
+        if confirm(&question) {
+            build::delete(
+                id.to_string(),
+                false,
+                None,
+                false,
+                config_file,
+                config::read_config(config_file.to_string()),
+            );
+            info!("Deleted {}", id);
+            cleaned = true;
+        }
+    }
+
+    if !cleaned {
+        info!("Nothing to clean up");
+    }
+}
+
+// the dirs of the installs the default and the projects `erlup switch` recorded
+// resolve to, going through the same lookup as the shims, and those projects
+// were last seen resolving to
+fn referenced_installs(config: &Ini) -> HashSet<String> {
+    let default = config::lookup("erlup", "default".to_string(), config);
+    let projects = config::get_projects(config)
+        .into_iter()
+        .filter_map(|(project_dir, _)| config::project_erlang(project_dir));
+    let resolved = config::get_resolved(config)
+        .into_iter()
+        .filter(|(project_dir, _)| Path::new(project_dir).is_dir())
+        .map(|(_, id)| id.to_string());
+
+    default
+        .into_iter()
+        .chain(projects)
+        .chain(resolved)
+        .filter_map(|erl| config::lookup_erl(&erl, config))
+        .collect()
+}
+
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = stdout().flush();
+    let mut answer = String::new();
+    match stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
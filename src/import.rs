@@ -0,0 +1,126 @@
+use clap::ValueEnum;
+use glob::glob;
+use ini::Ini;
+use std::env;
+use std::fs::read_to_string;
+use std::path::*;
+use std::process;
+
+use crate::build;
+use crate::config;
+
+/// Other version managers whose Erlang installs can be imported.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Tool {
+    Kerl,
+    Asdf,
+}
+
+/// `erlup import-from`, registers every install of `tool` under the name the
+/// tool gave it, linking to it or, with `copy`, copying it into erlup's dir.
+pub fn run(tool: Tool, copy: bool, config_file: &str, config: &Ini) {
+    let source = format!("{:?}", tool).to_lowercase();
+    let installs = match tool {
+        Tool::Kerl => kerl_installs(),
+        Tool::Asdf => asdf_installs(),
+    };
+    if installs.is_empty() {
+        error!("No {} installs found", source);
+        process::exit(1);
+    }
+
+    let mut imported = 0;
+    for (id, path) in installs.iter() {
+        if let Err(e) = build::external_install(path) {
+            info!("Skipping {}: {}", id, e);
+            continue;
+        }
+        // each import changes the config, so it's read again for the next one
+        let config = Ini::load_from_file(config_file).unwrap_or_else(|_| config.clone());
+        if !build::id_available(id, &config) {
+            info!(
+                "Skipping {}: it's already the id or alias of an install",
+                id
+            );
+            continue;
+        }
+
+        if copy {
+            build::copy_install(id, path, config_file, &config);
+        } else {
+            build::link(id, path, config_file, &config);
+        }
+        config::update_install_option(id, "imported_from", &source, config_file);
+        imported += 1;
+    }
+
+    info!(
+        "Imported {} of {} {} installs",
+        imported,
+        installs.len(),
+        source
+    );
+}
+
+fn home_dir() -> PathBuf {
+    dirs::home_dir().unwrap_or_else(|| {
+        error!("no home directory available");
+        process::exit(1)
+    })
+}
+
+// kerl lists every install, wherever it was installed to, in otp_installations
+// as `<name> <path>` lines, the ones in its default location are picked up too
+fn kerl_installs() -> Vec<(String, PathBuf)> {
+    let base = match env::var("KERL_BASE_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => home_dir().join(".kerl"),
+    };
+
+    let mut installs: Vec<(String, PathBuf)> = read_to_string(base.join("otp_installations"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let (name, path) = line.trim().split_once(' ')?;
+            Some((name.to_string(), PathBuf::from(path.trim())))
+        })
+        .collect();
+
+    for path in installs_in(&base.join("installations")) {
+        if !installs.iter().any(|(_, p)| *p == path) {
+            installs.push((file_name(&path), path));
+        }
+    }
+    installs
+}
+
+// asdf installs every version to installs/erlang/<version> under its data dir
+fn asdf_installs() -> Vec<(String, PathBuf)> {
+    let base = match env::var("ASDF_DATA_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => home_dir().join(".asdf"),
+    };
+
+    installs_in(&base.join("installs").join("erlang"))
+        .into_iter()
+        .map(|path| (file_name(&path), path))
+        .collect()
+}
+
+fn installs_in(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = match glob(dir.join("*").to_str().unwrap()) {
+        Ok(paths) => paths
+            .filter_map(Result::ok)
+            .filter(|p| p.is_dir())
+            .collect(),
+        Err(_) => vec![],
+    };
+    paths.sort();
+    paths
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
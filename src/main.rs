@@ -13,7 +13,16 @@ use std::process;
 
 mod build;
 mod config;
+mod detect;
+mod doctor;
+mod elixir;
 mod erl;
+mod gc;
+mod import;
+mod rebar3;
+mod repo;
+mod verify;
+mod version;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -34,11 +43,14 @@ enum SubCommands {
     /// List installed Erlangs
     List,
 
-    /// Fetch latest tags for repo
-    Fetch(RepoArgs),
+    /// Show details of an installed Erlang
+    Info(IdArgs),
+
+    /// Fetch latest tags and branches for repo
+    Fetch(FetchArgs),
 
     /// List available tags to build for a repo
-    Tags(RepoArgs),
+    Tags(TagsArgs),
 
     /// List available branches to build for a repo
     Branches(RepoArgs),
@@ -50,13 +62,55 @@ enum SubCommands {
     Default(IdArgs),
 
     /// Deletes an Erlang by id
-    Delete(IdArgs),
+    Delete(DeleteArgs),
+
+    /// Give an Erlang an alias that can be used in place of its id
+    Alias(AliasArgs),
+
+    /// Rename an Erlang's id
+    Rename(RenameArgs),
+
+    /// Register an Erlang installed some other way, without copying it
+    Link(LinkArgs),
+
+    /// Register the Erlangs installed by kerl or asdf
+    ImportFrom(ImportFromArgs),
 
     /// Build and Erlang by branch of tag name
     Build(BuildArgs),
 
+    /// Remove the build trees kept from failed builds or --keep-build-dir
+    Clean,
+
+    /// Show the disk space used by installs, repos and build trees
+    Du,
+
+    /// Remove orphaned installs and config entries, offering to remove unused installs
+    Gc,
+
     /// Update repos to the config
     Repo(RepoSubCommands),
+
+    /// Build and manage Elixir installs
+    Elixir(ElixirSubCommands),
+
+    /// Manage the rebar3 of an Erlang install
+    Rebar3(Rebar3SubCommands),
+
+    /// Check the host for the tools and libraries needed to build Erlang
+    Doctor,
+
+    /// Check that an installed Erlang is still intact and runs
+    Verify(VerifyArgs),
+
+    /// Detect the Erlang version this project needs from its build and CI files
+    Detect(DetectArgs),
+
+    /// List installs with a newer patch release available in their repo
+    Outdated,
+
+    /// Build the newest patch release of an install's major version
+    Upgrade(UpgradeArgs),
 }
 
 #[derive(Args)]
@@ -66,15 +120,141 @@ struct RepoArgs {
     repo: Option<String>,
 }
 
+#[derive(Args)]
+struct FetchArgs {
+    /// Which Erlang repo to use for command
+    #[arg(short, long, conflicts_with = "all")]
+    repo: Option<String>,
+
+    /// Fetch every configured repo
+    #[arg(short, long)]
+    all: bool,
+}
+
+#[derive(Args)]
+struct TagsArgs {
+    /// Which Erlang repo to use for command
+    #[arg(short, long)]
+    repo: Option<String>,
+
+    /// Only list versions of this major release
+    #[arg(long)]
+    major: Option<u32>,
+
+    /// Include release candidates
+    #[arg(long)]
+    include_rc: bool,
+
+    /// Maximum number of versions to list
+    #[arg(short, long)]
+    limit: Option<usize>,
+}
+
 #[derive(Args)]
 struct IdArgs {
     /// Id of the Erlang
     id: String,
 }
 
+#[derive(Args)]
+struct VerifyArgs {
+    /// Id of the Erlang
+    id: String,
+
+    /// Also compare files against the hashes recorded at install time
+    #[arg(long)]
+    hashes: bool,
+}
+
+#[derive(Args)]
+struct DetectArgs {
+    /// Write the compatible Erlang to ./erlup.config
+    #[arg(short, long)]
+    write: bool,
+}
+
+#[derive(Args)]
+struct UpgradeArgs {
+    /// Id of the Erlang to upgrade
+    id: String,
+
+    /// Id to give the upgraded Erlang build, defaults to the new tag
+    #[arg(short, long)]
+    new_id: Option<String>,
+
+    /// Make the upgraded Erlang the default if the old one was
+    #[arg(short, long)]
+    default: bool,
+
+    /// Update the erlup.config of projects switched to the old Erlang
+    #[arg(short, long)]
+    projects: bool,
+}
+
+#[derive(Args)]
+struct DeleteArgs {
+    /// Id of the Erlang to delete
+    id: String,
+
+    /// Delete the Erlang even if it's the default, leaving no default set
+    #[arg(short, long)]
+    force: bool,
+
+    /// Id of the Erlang to make the default when deleting the default
+    #[arg(long, value_name = "ID")]
+    new_default: Option<String>,
+
+    /// Show what would be deleted without deleting anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct AliasArgs {
+    /// Alias to use in erlup.config files or as the default, eg. 26
+    alias: String,
+
+    /// Id of the Erlang the alias refers to
+    #[arg(required_unless_present = "delete")]
+    id: Option<String>,
+
+    /// Remove the alias instead
+    #[arg(short, long, conflicts_with = "id")]
+    delete: bool,
+}
+
+#[derive(Args)]
+struct RenameArgs {
+    /// Id of the Erlang to rename
+    id: String,
+
+    /// New id for the Erlang
+    new_id: String,
+}
+
+#[derive(Args)]
+struct LinkArgs {
+    /// Id to give the Erlang
+    id: String,
+
+    /// Directory of the Erlang install, the one containing bin/erl
+    path: PathBuf,
+}
+
+#[derive(Args)]
+struct ImportFromArgs {
+    /// Tool that installed the Erlangs
+    #[arg(value_enum)]
+    tool: import::Tool,
+
+    /// Copy the installs instead of linking to them where they are
+    #[arg(long)]
+    copy: bool,
+}
+
 #[derive(Args)]
 struct BuildArgs {
-    /// Branch of tag of the Erlang repo
+    /// Tag, branch, remote branch, commit or other ref of the Erlang repo to build
     git_ref: String,
 
     /// Id to give the Erlang build
@@ -88,6 +268,27 @@ struct BuildArgs {
     /// Forces a build disregarding any previously existing ones
     #[arg(short, long)]
     force: Option<bool>,
+
+    /// Keep the build tree as a git worktree and only rebuild what changed on the next build of the same id
+    #[arg(long)]
+    incremental: bool,
+
+    /// Keep the build tree after a successful build, failed builds always keep theirs
+    #[arg(long)]
+    keep_build_dir: bool,
+
+    /// Extra option to pass to ./configure, appended to the configured defaults
+    #[arg(long = "configure-opt", value_name = "OPT", allow_hyphen_values = true)]
+    configure_opts: Vec<String>,
+
+    /// Extra emulator flavors to build and install, comma separated
+    #[arg(
+        long = "flavor",
+        value_name = "FLAVOR",
+        value_enum,
+        value_delimiter = ','
+    )]
+    flavors: Vec<build::Flavor>,
 }
 
 #[derive(Args)]
@@ -101,11 +302,105 @@ enum RepoCmds {
     /// Add repo to the configuration
     Add(RepoAddArgs),
 
+    /// Change the url of a repo
+    SetUrl(RepoSetUrlArgs),
+
+    /// Rename a repo, moving its clone
+    Rename(RepoRenameArgs),
+
+    /// Show details of a repo
+    Show(RepoShowArgs),
+
     /// Remove repo from the configuration
     Rm(RepoRmArgs),
 
     /// List available repos
-    Ls,
+    Ls(RepoLsArgs),
+}
+
+#[derive(Args)]
+struct ElixirSubCommands {
+    #[command(subcommand)]
+    cmd: ElixirCmds,
+}
+
+#[derive(Subcommand)]
+enum ElixirCmds {
+    /// Build an Elixir by tag or branch name with an installed Erlang
+    Build(ElixirBuildArgs),
+
+    /// List installed Elixirs
+    List,
+
+    /// Switch Elixir to use in this directory by id
+    Switch(ElixirIdArgs),
+
+    /// Set default Elixir to use by id
+    Default(ElixirIdArgs),
+
+    /// Deletes an Elixir by id
+    Delete(ElixirIdArgs),
+}
+
+#[derive(Args)]
+struct ElixirIdArgs {
+    /// Id of the Elixir
+    id: String,
+}
+
+#[derive(Args)]
+struct ElixirBuildArgs {
+    /// Tag, branch, commit or other ref of the Elixir repo to build
+    git_ref: String,
+
+    /// Id to give the Elixir build
+    #[arg(short, long)]
+    id: Option<String>,
+
+    /// Which Elixir repo to use for command
+    #[arg(short, long, default_value = "elixir")]
+    repo: String,
+
+    /// Id of the Erlang to build with, defaults to the one used in this directory
+    #[arg(short, long)]
+    erlang: Option<String>,
+
+    /// Forces a build disregarding any previously existing ones
+    #[arg(short, long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct Rebar3SubCommands {
+    #[command(subcommand)]
+    cmd: Rebar3Cmds,
+}
+
+#[derive(Subcommand)]
+enum Rebar3Cmds {
+    /// Build rebar3 with an Erlang install and add it to that install
+    #[command(disable_version_flag = true)]
+    Install(Rebar3InstallArgs),
+}
+
+#[derive(Args)]
+struct Rebar3InstallArgs {
+    /// Tag or other ref of the rebar3 repo to build, `latest` or a series like `~3.22`
+    #[arg(
+        short = 'v',
+        long = "version",
+        value_name = "VERSION",
+        default_value = "latest"
+    )]
+    rebar3_version: String,
+
+    /// Id of the Erlang to install rebar3 for, defaults to the one used in this directory
+    #[arg(short, long)]
+    erlang: Option<String>,
+
+    /// Which rebar3 repo to use for command
+    #[arg(short, long, default_value = "rebar3")]
+    repo: String,
 }
 
 #[derive(Args)]
@@ -115,12 +410,51 @@ struct RepoAddArgs {
 
     /// Url of the git repo for the repo
     repo: String,
+
+    /// Clone without blobs, fetching them as builds need them
+    #[arg(long)]
+    partial: bool,
+}
+
+#[derive(Args)]
+struct RepoSetUrlArgs {
+    /// Name of the repo to update
+    name: String,
+
+    /// Url of the git repo for the repo
+    repo: String,
+}
+
+#[derive(Args)]
+struct RepoLsArgs {
+    /// Show the disk usage of each repo
+    #[arg(short, long)]
+    sizes: bool,
+}
+
+#[derive(Args)]
+struct RepoRenameArgs {
+    /// Current name of the repo
+    name: String,
+
+    /// New name for the repo
+    new_name: String,
+}
+
+#[derive(Args)]
+struct RepoShowArgs {
+    /// Name of the repo to show
+    name: String,
 }
 
 #[derive(Args)]
 struct RepoRmArgs {
     /// Name of the repo to remove
     name: String,
+
+    /// Also delete the repo's clone
+    #[arg(long)]
+    purge: bool,
 }
 
 fn repo_or_default(maybe_repo: Option<String>) -> String {
@@ -144,19 +478,41 @@ fn handle_command(bin_path: PathBuf) {
             debug!("running update links");
             let dir = &config::lookup_cache_dir(&config);
             let links_dir = Path::new(dir).join("bin");
-            build::update_bins(bin_path.as_path(), links_dir.as_path());
+            build::update_bins(bin_path.as_path(), links_dir.as_path(), &config);
         }
         SubCommands::List => {
             debug!("running list");
             config::list();
         }
-        SubCommands::Fetch(RepoArgs { repo }) => {
-            debug!("running fetch: repo={:?}", repo);
-            build::fetch(repo.clone(), config);
+        SubCommands::Info(IdArgs { id }) => {
+            debug!("running info: id={}", id);
+            build::info(id, config);
         }
-        SubCommands::Tags(RepoArgs { repo }) => {
-            debug!("running list tags: repo={:?}", repo);
-            build::tags(repo_or_default(repo.clone()), config);
+        SubCommands::Fetch(FetchArgs { repo, all }) => {
+            debug!("running fetch: repo={:?} all={}", repo, all);
+            if *all {
+                build::fetch_all(config);
+            } else {
+                build::fetch(repo.clone(), config);
+            }
+        }
+        SubCommands::Tags(TagsArgs {
+            repo,
+            major,
+            include_rc,
+            limit,
+        }) => {
+            debug!(
+                "running list tags: repo={:?} major={:?} include_rc={} limit={:?}",
+                repo, major, include_rc, limit
+            );
+            build::tags(
+                repo_or_default(repo.clone()),
+                *major,
+                *include_rc,
+                *limit,
+                config,
+            );
         }
         SubCommands::Branches(RepoArgs { repo }) => {
             debug!("running list branches: repo={:?}", repo);
@@ -170,17 +526,55 @@ fn handle_command(bin_path: PathBuf) {
             debug!("running default: id={}", id);
             config::set_default(id.as_str());
         }
-        SubCommands::Delete(IdArgs { id }) => {
+        SubCommands::Delete(DeleteArgs {
+            id,
+            force,
+            new_default,
+            dry_run,
+        }) => {
             debug!("running delete: id={}", id);
-            build::delete(id.clone(), &config_file, config);
+            build::delete(
+                id.clone(),
+                *force,
+                new_default.as_deref(),
+                *dry_run,
+                &config_file,
+                config,
+            );
+        }
+        SubCommands::Alias(AliasArgs { alias, id, delete }) => {
+            debug!("running alias: alias={} id={:?}", alias, id);
+            match id {
+                Some(id) if !delete => config::alias(alias, id, &config_file),
+                _ => config::unalias(alias, &config_file),
+            }
+        }
+        SubCommands::Rename(RenameArgs { id, new_id }) => {
+            debug!("running rename: id={} new_id={}", id, new_id);
+            build::rename(id, new_id, &config_file, config);
+        }
+        SubCommands::Link(LinkArgs { id, path }) => {
+            debug!("running link: id={} path={:?}", id, path);
+            build::link(id, path, &config_file, &config);
+        }
+        SubCommands::ImportFrom(ImportFromArgs { tool, copy }) => {
+            debug!("running import-from: tool={:?} copy={}", tool, copy);
+            import::run(*tool, *copy, &config_file, &config);
         }
         SubCommands::Build(BuildArgs {
             git_ref,
             id,
             repo,
             force,
+            incremental,
+            keep_build_dir,
+            configure_opts,
+            flavors,
         }) => {
-            debug!("running build: {} {:?} {:?} {:?}", git_ref, id, repo, force);
+            debug!(
+                "running build: {} {:?} {:?} {:?} {:?} {:?}",
+                git_ref, id, repo, force, configure_opts, flavors
+            );
 
             let repo = repo_or_default(repo.clone());
             let repo_url = &config::lookup("repos", repo.clone(), &config).unwrap_or_else(|| {
@@ -194,42 +588,178 @@ fn handle_command(bin_path: PathBuf) {
             let dir = &config::lookup_cache_dir(&config);
             let repo_dir = Path::new(dir).join("repos").join(repo.clone());
 
-            let git_ref = match git_ref.as_str() {
-                "latest" => build::latest_tag(repo_dir),
-                _ => git_ref.clone(),
+            // resolve `latest`, `latest:26`, `~26.2` and `latest-rc` to a tag
+            let git_ref = match version::LatestSpec::parse(git_ref) {
+                Some(spec) => {
+                    let tag = build::latest_tag(repo_dir, &spec);
+                    info!("Resolved {} to {}", git_ref, tag);
+                    tag
+                }
+                None => git_ref.clone(),
             };
 
-            let id = id.clone().unwrap_or(git_ref.clone());
             let force = match force {
                 Some(f) => *f,
                 None => false,
             };
-            build::run(
+            // the install is still added when broken, but the build failed
+            let healthy = build::run(
                 bin_path,
                 git_ref,
-                id,
+                id.clone(),
                 repo,
                 repo_url.clone(),
                 force,
+                *incremental,
+                *keep_build_dir,
+                configure_opts,
+                flavors,
+                &config_file,
+                config,
+            );
+            if !healthy {
+                process::exit(1);
+            }
+        }
+        SubCommands::Clean => {
+            debug!("running clean");
+            build::clean(&config);
+        }
+        SubCommands::Du => {
+            debug!("running du");
+            gc::du(&config);
+        }
+        SubCommands::Gc => {
+            debug!("running gc");
+            gc::gc(&config_file, config);
+        }
+        SubCommands::Doctor => {
+            debug!("running doctor");
+            doctor::run(bin_path.as_path(), &config);
+        }
+        SubCommands::Verify(VerifyArgs { id, hashes }) => {
+            debug!("running verify: id={} hashes={}", id, hashes);
+            verify::run(id, *hashes, &config_file, &config);
+        }
+        SubCommands::Detect(DetectArgs { write }) => {
+            debug!("running detect: write={}", write);
+            detect::run(*write, &config);
+        }
+        SubCommands::Outdated => {
+            debug!("running outdated");
+            build::outdated(config);
+        }
+        SubCommands::Upgrade(UpgradeArgs {
+            id,
+            new_id,
+            default,
+            projects,
+        }) => {
+            debug!(
+                "running upgrade: id={} new_id={:?} default={} projects={}",
+                id, new_id, default, projects
+            );
+            build::upgrade(
+                bin_path,
+                id,
+                new_id.clone(),
+                *default,
+                *projects,
                 &config_file,
                 config,
             );
         }
         SubCommands::Repo(repo_sub_cmd) => match &repo_sub_cmd.cmd {
-            RepoCmds::Add(RepoAddArgs { name, repo }) => {
-                debug!("running repo add: name={} repo={}", name, repo);
-                config::add_repo(name, repo, &config_file, config);
+            RepoCmds::Add(RepoAddArgs {
+                name,
+                repo,
+                partial,
+            }) => {
+                debug!(
+                    "running repo add: name={} repo={} partial={}",
+                    name, repo, partial
+                );
+                repo::add(name, repo, *partial, &config_file, config);
             }
-            RepoCmds::Rm(RepoRmArgs { name }) => {
-                debug!("running repo rm: name={}", name);
-                config::delete_repo(name, &config_file, config);
+            RepoCmds::SetUrl(RepoSetUrlArgs { name, repo }) => {
+                debug!("running repo set-url: name={} repo={}", name, repo);
+                repo::set_url(name, repo, &config_file, config);
             }
-            RepoCmds::Ls => {
-                debug!("running repo ls");
-                let repos = config::get_repos(&config);
-                for (id, url) in repos {
-                    println!("{} -> {}", id, url);
-                }
+            RepoCmds::Rename(RepoRenameArgs { name, new_name }) => {
+                debug!("running repo rename: name={} new_name={}", name, new_name);
+                repo::rename(name, new_name, &config_file, config);
+            }
+            RepoCmds::Show(RepoShowArgs { name }) => {
+                debug!("running repo show: name={}", name);
+                repo::show(name, &config);
+            }
+            RepoCmds::Rm(RepoRmArgs { name, purge }) => {
+                debug!("running repo rm: name={} purge={}", name, purge);
+                repo::rm(name, *purge, &config_file, config);
+            }
+            RepoCmds::Ls(RepoLsArgs { sizes }) => {
+                debug!("running repo ls: sizes={}", sizes);
+                repo::ls(*sizes, &config);
+            }
+        },
+        SubCommands::Rebar3(rebar3_sub_cmd) => match &rebar3_sub_cmd.cmd {
+            Rebar3Cmds::Install(Rebar3InstallArgs {
+                rebar3_version,
+                erlang,
+                repo,
+            }) => {
+                debug!(
+                    "running rebar3 install: version={} erlang={:?} repo={}",
+                    rebar3_version, erlang, repo
+                );
+                rebar3::install(
+                    rebar3_version,
+                    erlang.as_deref(),
+                    repo,
+                    bin_path.as_path(),
+                    &config_file,
+                    &config,
+                );
+            }
+        },
+        SubCommands::Elixir(elixir_sub_cmd) => match &elixir_sub_cmd.cmd {
+            ElixirCmds::Build(ElixirBuildArgs {
+                git_ref,
+                id,
+                repo,
+                erlang,
+                force,
+            }) => {
+                debug!(
+                    "running elixir build: git_ref={} id={:?} repo={} erlang={:?} force={}",
+                    git_ref, id, repo, erlang, force
+                );
+                elixir::build(
+                    bin_path.as_path(),
+                    git_ref,
+                    id.clone(),
+                    repo,
+                    erlang.as_deref(),
+                    *force,
+                    &config_file,
+                    &config,
+                );
+            }
+            ElixirCmds::List => {
+                debug!("running elixir list");
+                elixir::list(&config);
+            }
+            ElixirCmds::Switch(ElixirIdArgs { id }) => {
+                debug!("running elixir switch: id={}", id);
+                elixir::switch(id, &config);
+            }
+            ElixirCmds::Default(ElixirIdArgs { id }) => {
+                debug!("running elixir default: id={}", id);
+                elixir::set_default(id, &config_file, &config);
+            }
+            ElixirCmds::Delete(ElixirIdArgs { id }) => {
+                debug!("running elixir delete: id={}", id);
+                elixir::delete(id, &config_file, &config);
             }
         },
     }
@@ -276,9 +806,16 @@ fn main() {
                 process::exit(1)
             }
         }
+    } else if let Some(x) = elixir::BINS
+        .iter()
+        .find(|&&x| f.eq(Path::new(x).file_name().unwrap()))
+    {
+        let bin = Path::new(x).file_name().unwrap();
+        elixir::run(bin.to_str().unwrap(), args);
     } else {
         match build::BINS
             .iter()
+            .chain([rebar3::BIN].iter())
             .find(|&&x| f.eq(Path::new(x).file_name().unwrap()))
         {
             Some(x) => {
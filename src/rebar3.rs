@@ -0,0 +1,118 @@
+use console::style;
+use indicatif::HumanDuration;
+use ini::Ini;
+use std::fs::*;
+use std::path::*;
+use std::process;
+use std::time::Instant;
+
+use crate::build::{self, BuildDir, BuildResult, CHECKMARK};
+use crate::config;
+use crate::version::LatestSpec;
+
+pub const BIN: &str = "bin/rebar3";
+
+/// `erlup rebar3 install`, builds rebar3 `version` of the repo `repo` with the
+/// Erlang `erlang`, or the one in use here when not given, and puts it in that
+/// install's `dist/bin` so the `rebar3` shim finds it.
+pub fn install(
+    version: &str,
+    erlang: Option<&str>,
+    repo: &str,
+    bin_path: &Path,
+    config_file: &str,
+    config: &Ini,
+) {
+    let erl_dir = match erlang {
+        Some(erlang) => config::lookup_erl(erlang, config).unwrap_or_else(|| {
+            error!("{} is not a configured Erlang install", erlang);
+            process::exit(1)
+        }),
+        None => config::erl_to_use(),
+    };
+    let id = config::install_id(&erl_dir, config).unwrap_or_else(|| {
+        error!("No configured Erlang install found at {}", erl_dir);
+        process::exit(1)
+    });
+    if config::is_linked(&id, config) {
+        error!("{} is a linked install, erlup doesn't add files to it", id);
+        process::exit(1);
+    }
+
+    let dir = config::lookup_cache_dir(config);
+    let repo_url = config::lookup("repos", repo.to_string(), config).unwrap_or_else(|| {
+        error!("Repo {} not found in config", repo);
+        error!(
+            "To add it: erlup repo add {} https://github.com/erlang/rebar3",
+            repo
+        );
+        process::exit(1)
+    });
+    let repo_dir = Path::new(dir).join("repos").join(repo);
+    if !repo_dir.is_dir() {
+        info!("Cloning repo {} to {}", repo_url, repo_dir.display());
+        build::clone_repo(&repo_url, repo_dir.to_owned(), config);
+    }
+
+    // rebar3's tags are plain versions, so `latest` and `~3.22` pick from them too
+    let git_ref = match LatestSpec::parse(version) {
+        Some(spec) => build::latest_tag(repo_dir.to_owned(), &spec),
+        None => version.to_string(),
+    };
+    let resolved = build::resolve_ref(&repo_dir, &git_ref);
+    info!(
+        "Building rebar3 {} ({}) for {}",
+        git_ref, resolved.commit, id
+    );
+
+    let started = Instant::now();
+    let pb = build::spinner();
+    let build_dir = build::temp_build_dir(&format!("rebar3-{}", id), config);
+    let src_dir = build_dir.path().to_path_buf();
+
+    pb.set_message(format!("Checking out {}", git_ref));
+    build::checkout(&src_dir, &repo_dir, &resolved.commit, &pb);
+    pb.println(format!(
+        " {} Checking out {} (done in {})",
+        CHECKMARK,
+        git_ref,
+        HumanDuration(started.elapsed())
+    ));
+
+    // bootstrap is an escript, compiling rebar3 with the first one in PATH
+    build::put_erlang_on_path(&erl_dir);
+    match build::exec("./bootstrap", &vec![], &src_dir, Instant::now(), &pb) {
+        Ok(BuildResult::Success) => (),
+        Ok(BuildResult::Fail) => build::abort_build(build_dir, &pb),
+        Err(e) => {
+            error!("build failed: {}", e);
+            build::abort_build(build_dir, &pb)
+        }
+    }
+
+    let target = Path::new(&erl_dir).join(BIN);
+    if let Err(e) = copy(src_dir.join("rebar3"), &target) {
+        error!("unable to copy rebar3 to {}: {}", target.display(), e);
+        build::abort_build(build_dir, &pb)
+    }
+    if let BuildDir::Temp(dir) = build_dir {
+        drop(dir);
+    }
+
+    if let Some(install_dir) = Path::new(&erl_dir).parent() {
+        build::setup_links(install_dir, Path::new(&erl_dir));
+    }
+    // the rebar3 shim is only linked once an install has rebar3
+    let links_dir = Path::new(dir).join("bin");
+    build::update_bins(bin_path, &links_dir, config);
+    config::update_install_option(&id, "rebar3", &git_ref, config_file);
+
+    pb.finish_and_clear();
+    println!(
+        "{} rebar3 {} for {} in {}",
+        style("Installed").green().bold(),
+        git_ref,
+        id,
+        HumanDuration(started.elapsed())
+    );
+}
@@ -0,0 +1,369 @@
+use indicatif::{HumanBytes, HumanDuration};
+use ini::Ini;
+use std::fs::*;
+use std::path::*;
+use std::process;
+use std::process::Command;
+
+use crate::config;
+
+fn repo_dir(name: &str, config: &Ini) -> PathBuf {
+    let dir = config::lookup_cache_dir(config);
+    Path::new(dir).join("repos").join(name)
+}
+
+fn lookup_or_exit(name: &str, config: &Ini) -> String {
+    config::lookup("repos", name.to_string(), config).unwrap_or_else(|| {
+        error!("Repo {} not found in config", name);
+        error!("To list repos: erlup repo ls");
+        process::exit(1)
+    })
+}
+
+// make sure the url points at a git repo we can reach before saving it
+fn validate_url(url: &str) {
+    let output = Command::new("git")
+        .args(["ls-remote", url, "HEAD"])
+        .output()
+        .unwrap_or_else(|e| {
+            error!("git ls-remote failed: {}", e);
+            process::exit(1)
+        });
+
+    if !output.status.success() {
+        error!(
+            "{} is not a reachable git repo: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        process::exit(1);
+    }
+}
+
+pub fn add(name: &str, url: &str, partial: bool, config_file: &str, config: Ini) {
+    if let Some(existing) = config::lookup("repos", name.to_string(), &config) {
+        error!("Repo {} already exists with url {}", name, existing);
+        error!("To change its url: erlup repo set-url {} <url>", name);
+        process::exit(1);
+    }
+
+    validate_url(url);
+    config::add_repo(name, url, config_file, config);
+    if partial {
+        config::update_repo_option(name, "partial_clone", "true", config_file);
+    }
+    info!("Added repo {} -> {}", name, url);
+}
+
+pub fn set_url(name: &str, url: &str, config_file: &str, config: Ini) {
+    lookup_or_exit(name, &config);
+    validate_url(url);
+
+    // keep an existing clone pointing at the same remote as the config
+    let dir = repo_dir(name, &config);
+    if dir.is_dir() {
+        let output = Command::new("git")
+            .args(["remote", "set-url", "origin", url])
+            .current_dir(&dir)
+            .output()
+            .unwrap_or_else(|e| {
+                error!("git remote set-url failed: {}", e);
+                process::exit(1)
+            });
+
+        if !output.status.success() {
+            error!(
+                "updating remote of {:?} failed: {}",
+                dir,
+                String::from_utf8_lossy(&output.stderr)
+            );
+            process::exit(1);
+        }
+    }
+
+    config::add_repo(name, url, config_file, config);
+    info!("Repo {} now points to {}", name, url);
+}
+
+pub fn rename(old: &str, new: &str, config_file: &str, config: Ini) {
+    lookup_or_exit(old, &config);
+    if config::lookup("repos", new.to_string(), &config).is_some() {
+        error!("Repo {} already exists", new);
+        process::exit(1);
+    }
+
+    let old_dir = repo_dir(old, &config);
+    let new_dir = repo_dir(new, &config);
+    if old_dir.is_dir() {
+        if new_dir.exists() {
+            error!("Directory for repo {} already exists: {:?}", new, new_dir);
+            process::exit(1);
+        }
+        rename_dir(&old_dir, &new_dir);
+    }
+    rename_in_store(old, new, &config);
+
+    config::rename_repo(old, new, config_file, config);
+    info!("Renamed repo {} to {}", old, new);
+}
+
+fn rename_dir(from: &Path, to: &Path) {
+    std::fs::rename(from, to).unwrap_or_else(|e| {
+        error!("unable to move {:?} to {:?}: {}", from, to, e);
+        process::exit(1)
+    });
+}
+
+pub fn show(name: &str, config: &Ini) {
+    let url = lookup_or_exit(name, config);
+    let dir = repo_dir(name, config);
+
+    println!("name: {}", name);
+    println!("url: {}", url);
+    println!("dir: {}", dir.display());
+
+    if !dir.is_dir() {
+        println!("not cloned yet, run `erlup fetch -r {}`", name);
+        return;
+    }
+
+    if let Some(remote) = git_output(&["remote", "get-url", "origin"], &dir) {
+        println!("remote: {}", remote.trim());
+    }
+
+    // FETCH_HEAD is written on every fetch, a fresh clone only has the clone time
+    let fetched = ["FETCH_HEAD", "HEAD"]
+        .iter()
+        .filter_map(|f| metadata(dir.join(".git").join(f)).ok())
+        .filter_map(|m| m.modified().ok())
+        .next();
+    match fetched.and_then(|t| t.elapsed().ok()) {
+        Some(elapsed) => println!("last fetched: {} ago", HumanDuration(elapsed)),
+        None => println!("last fetched: unknown"),
+    }
+
+    let tags = git_output(&["tag"], &dir)
+        .map(|tags| tags.lines().count())
+        .unwrap_or(0);
+    println!("tags: {}", tags);
+
+    let clone_type = if uses_store(&dir) {
+        "shares objects with the other repos"
+    } else if git_output(&["config", "remote.origin.partialclonefilter"], &dir).is_some() {
+        "partial clone"
+    } else {
+        "full clone"
+    };
+    println!("clone: {}", clone_type);
+    println!("disk usage: {}", HumanBytes(dir_size(&dir)));
+}
+
+pub fn rm(name: &str, purge: bool, config_file: &str, config: Ini) {
+    lookup_or_exit(name, &config);
+    let dir = repo_dir(name, &config);
+
+    // remove the clone before the config entry, so a failure doesn't leave it orphaned
+    if purge && dir.exists() {
+        remove_dir_all(&dir).unwrap_or_else(|e| {
+            error!("unable to delete {:?} due to {}", dir, e);
+            process::exit(1)
+        });
+        info!("Deleted {}", dir.display());
+        // only once the clone is gone, it may still be borrowing objects from the store
+        remove_from_store(name, &config);
+    } else if dir.exists() {
+        info!(
+            "Leaving clone at {}, use --purge to delete it",
+            dir.display()
+        );
+    }
+
+    config::delete_repo(&name.to_string(), config_file, config);
+}
+
+fn git_output(args: &[&str], dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        debug!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// partial clones are opt-in, for every repo in `[erlup]` or per repo in `[repo.<name>]`
+pub fn partial_clone(name: &str, config: &Ini) -> bool {
+    config::lookup_repo_option(name, "partial_clone", config)
+        .and_then(|v| config::parse_bool(&v))
+        .or_else(|| config::lookup_bool("erlup", "partial_clone", config))
+        .unwrap_or(false)
+}
+
+// sharing objects between repos is on unless `shared_objects=false` in `[erlup]`
+pub fn shared_objects(config: &Ini) -> bool {
+    config::lookup_bool("erlup", "shared_objects", config).unwrap_or(true)
+}
+
+pub fn shared_store_dir(config: &Ini) -> PathBuf {
+    let dir = config::lookup_cache_dir(config);
+    Path::new(dir).join("shared.git")
+}
+
+pub fn uses_store(repo_dir: &Path) -> bool {
+    repo_dir.join(".git/objects/info/alternates").exists()
+}
+
+/// Fetches a repo's branches and tags into the shared bare store, under
+/// `refs/remotes/<name>/` so forks with the same tag names don't clash, and
+/// returns the store's path.
+pub fn fetch_into_store(name: &str, url: &str, config: &Ini) -> PathBuf {
+    let store = shared_store_dir(config);
+    if !store.exists() {
+        run_git_in(&["init", "--bare", store.to_str().unwrap()], Path::new("."));
+    }
+
+    // pruning can drop the only store refs to objects another clone borrows
+    keep_borrowed_refs(&store, config);
+    let heads = format!("+refs/heads/*:refs/remotes/{}/heads/*", name);
+    let tags = format!("+refs/tags/*:refs/remotes/{}/tags/*", name);
+    run_git_in(
+        &[
+            "fetch",
+            "--no-tags",
+            "--prune",
+            url,
+            heads.as_str(),
+            tags.as_str(),
+        ],
+        &store,
+    );
+    store
+}
+
+// the store refs a repo's objects are kept by, its fetched refs and the refs of
+// its clone
+fn store_refs(name: &str) -> [String; 2] {
+    [
+        format!("refs/remotes/{}/", name),
+        format!("refs/borrowed/{}/", name),
+    ]
+}
+
+/// Copies the refs of every clone that borrows from the store into the store,
+/// under `refs/borrowed/<name>/`, so the objects they use stay reachable there
+/// when fetched refs are pruned or a repo is removed.
+fn keep_borrowed_refs(store: &Path, config: &Ini) {
+    for (name, _) in config::get_repos(config) {
+        let dir = repo_dir(name, config);
+        if !dir.is_dir() || !uses_store(&dir) {
+            continue;
+        }
+        let refspec = format!("+refs/*:refs/borrowed/{}/*", name);
+        let _ = git_output(
+            &[
+                "fetch",
+                "--no-tags",
+                "--prune",
+                dir.to_str().unwrap(),
+                refspec.as_str(),
+            ],
+            store,
+        );
+    }
+}
+
+// the store's copy of a removed repo's refs go too, so its objects can be pruned
+pub fn remove_from_store(name: &str, config: &Ini) {
+    let store = shared_store_dir(config);
+    if !store.exists() {
+        return;
+    }
+    for refs in store_refs(name) {
+        if let Some(output) = git_output(&["for-each-ref", "--format=%(refname)", &refs], &store) {
+            for refname in output.lines() {
+                let _ = git_output(&["update-ref", "-d", refname], &store);
+            }
+        }
+    }
+    keep_borrowed_refs(&store, config);
+}
+
+// a renamed repo's store refs move with it, `repo rm --purge` looks them up by name
+fn rename_in_store(old: &str, new: &str, config: &Ini) {
+    let store = shared_store_dir(config);
+    if !store.exists() {
+        return;
+    }
+    for (old_refs, new_refs) in store_refs(old).iter().zip(store_refs(new).iter()) {
+        if let Some(output) = git_output(&["for-each-ref", "--format=%(refname)", old_refs], &store)
+        {
+            for refname in output.lines() {
+                let renamed = refname.replacen(old_refs.as_str(), new_refs, 1);
+                if git_output(&["update-ref", &renamed, refname], &store).is_some() {
+                    let _ = git_output(&["update-ref", "-d", refname], &store);
+                }
+            }
+        }
+    }
+}
+
+fn run_git_in(args: &[&str], dir: &Path) {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap_or_else(|e| {
+            error!("git command failed: {}", e);
+            process::exit(1)
+        });
+
+    if !output.status.success() {
+        error!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        process::exit(1);
+    }
+}
+
+/// Size on disk of everything under `path`, not following symlinks.
+pub fn dir_size(path: &Path) -> u64 {
+    match symlink_metadata(path) {
+        Ok(m) if m.is_dir() => read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| dir_size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(m) => m.len(),
+        Err(_) => 0,
+    }
+}
+
+pub fn ls(sizes: bool, config: &Ini) {
+    for (name, url) in config::get_repos(config) {
+        if sizes {
+            let size = dir_size(&repo_dir(name, config));
+            println!("{} -> {} ({})", name, url, HumanBytes(size));
+        } else {
+            println!("{} -> {}", name, url);
+        }
+    }
+
+    let store = shared_store_dir(config);
+    if sizes && store.exists() {
+        println!("shared object store: {}", HumanBytes(dir_size(&store)));
+    }
+}
@@ -0,0 +1,373 @@
+use console::style;
+use glob::glob;
+use ini::Ini;
+use std::fs::*;
+use std::os::unix::fs::PermissionsExt;
+use std::path::*;
+use std::process;
+use std::process::Command;
+use tempdir::TempDir;
+
+use crate::build::{erlang_root, BINS, CHECKMARK, FAIL, WARNING};
+use crate::config;
+use crate::doctor;
+
+// applications that must load in every install, on top of any configured required apps
+const DEFAULT_APPS: [&str; 2] = ["crypto", "ssl"];
+
+const REQUIRED_BINS: [&str; 3] = ["bin/erl", "bin/erlc", "bin/escript"];
+
+// the version of OTP a tag like OTP-26.2.1 or OTP-27.0-rc1 should install,
+// branches and other refs have no expected version
+pub fn expected_otp_version(git_ref: &str) -> Option<String> {
+    let tag = git_ref.rsplit('/').next().unwrap_or(git_ref);
+    tag.strip_prefix("OTP-").map(str::to_string)
+}
+
+/// Boots the install's `erl` to check its version and that the applications
+/// load, then compiles a module with its `erlc`. Returns the problems found.
+pub fn smoke_test(
+    dist_dir: &Path,
+    expected_version: Option<&str>,
+    required_apps: &[String],
+) -> Vec<String> {
+    let (vsn, mut problems) = check_boot(dist_dir, required_apps);
+    if let (Some(expected), Some(vsn)) = (expected_version, vsn) {
+        if vsn != expected {
+            problems.push(format!(
+                "installed OTP version is {} but expected {}",
+                vsn, expected
+            ));
+        }
+    }
+
+    if let Err(e) = compile_module(dist_dir) {
+        problems.push(e);
+    }
+
+    problems
+}
+
+fn boot(dist_dir: &Path, apps: &[String]) -> Result<String, String> {
+    let erl = dist_dir.join("bin").join("erl");
+    let apps = apps
+        .iter()
+        .map(|app| format!("'{}'", app))
+        .collect::<Vec<String>>()
+        .join(",");
+    // loading the module named after the application makes sure any NIF it
+    // has, like crypto's, can be loaded as well
+    let script = format!(
+        "Release = erlang:system_info(otp_release), \
+         VsnFile = filename:join([code:root_dir(), \"releases\", Release, \"OTP_VERSION\"]), \
+         Vsn = case file:read_file(VsnFile) of {{ok, V}} -> string:strip(binary_to_list(V), right, $\\n); _ -> Release end, \
+         io:format(\"version ~s~n\", [Vsn]), \
+         Module = fun(App) -> \
+             case code:ensure_loaded(App) of \
+                 {{module, _}} -> ok; \
+                 {{error, nofile}} -> ok; \
+                 ModuleError -> ModuleError \
+             end \
+         end, \
+         Check = fun(App) -> \
+             case application:load(App) of \
+                 ok -> Module(App); \
+                 {{error, {{already_loaded, _}}}} -> Module(App); \
+                 LoadError -> LoadError \
+             end \
+         end, \
+         [io:format(\"app ~s ~w~n\", [App, Check(App)]) || App <- [{}]], \
+         halt().",
+        apps
+    );
+
+    let output = Command::new(&erl)
+        .args(["-noshell", "-eval", &script])
+        .output()
+        .map_err(|e| format!("unable to run {}: {}", erl.display(), e))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{} failed to boot: {}",
+            erl.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn compile_module(dist_dir: &Path) -> Result<(), String> {
+    let erlc = dist_dir.join("bin").join("erlc");
+    let dir = TempDir::new("erlup")
+        .map_err(|e| format!("failed creating temp directory for erlc check: {}", e))?;
+    let src = dir.path().join("erlup_smoke.erl");
+    write(
+        &src,
+        "-module(erlup_smoke).\n-export([ok/0]).\nok() -> ok.\n",
+    )
+    .map_err(|e| format!("failed writing {}: {}", src.display(), e))?;
+
+    let output = Command::new(&erlc)
+        .arg("-o")
+        .arg(dir.path())
+        .arg(&src)
+        .output()
+        .map_err(|e| format!("unable to run {}: {}", erlc.display(), e))?;
+
+    if !output.status.success() || !dir.path().join("erlup_smoke.beam").exists() {
+        return Err(format!(
+            "{} failed to compile a module: {}",
+            erlc.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks an existing install still works: the executables in `dist/bin` are
+/// there, it boots and loads its applications, the shared objects it needs can
+/// be resolved and, optionally, its files match the install time manifest.
+pub fn run(id: &str, check_hashes: bool, config_file: &str, config: &Ini) {
+    let dist_dir = config::lookup("erlangs", id.to_string(), config).unwrap_or_else(|| {
+        error!("{} is not a configured Erlang install", id);
+        process::exit(1)
+    });
+    let dist_dir = Path::new(&dist_dir);
+    let required_apps = match config::lookup_install_option(id, "repo", config) {
+        Some(repo) => config::required_apps(&repo, config),
+        None => config::required_apps("default", config),
+    };
+
+    let mut checks: Vec<(&str, Vec<String>)> = vec![
+        ("Executables", check_executables(dist_dir)),
+        ("Boot", check_boot(dist_dir, &required_apps).1),
+        ("Shared libraries", check_shared_libraries(dist_dir)),
+    ];
+    // there's no manifest for a linked install, erlup didn't install its files
+    if check_hashes && config::is_linked(id, config) {
+        println!(" {} Manifest, {} is a linked install", WARNING, id);
+    } else if check_hashes {
+        checks.push(("Manifest", check_manifest(dist_dir)));
+    }
+
+    let mut healthy = true;
+    for (name, problems) in checks.iter() {
+        if problems.is_empty() {
+            println!(" {} {}", CHECKMARK, name);
+        } else {
+            healthy = false;
+            println!(" {} {}", FAIL, name);
+            for problem in problems {
+                println!("    {}", problem);
+            }
+        }
+    }
+
+    let status = if healthy { "ok" } else { "broken" };
+    config::update_install_option(id, "status", status, config_file);
+
+    if !healthy {
+        println!("{} {} is broken", style("Verify failed:").red().bold(), id);
+        process::exit(1);
+    }
+    println!("{} {} is ok", style("Verified").green().bold(), id);
+}
+
+fn check_executables(dist_dir: &Path) -> Vec<String> {
+    let mut problems = vec![];
+    for &b in BINS.iter() {
+        let bin = dist_dir.join(b);
+        // not every release has every executable, only the core ones must exist
+        if !bin.exists() && !REQUIRED_BINS.contains(&b) {
+            continue;
+        }
+        match metadata(&bin) {
+            Ok(m) if m.is_file() && m.permissions().mode() & 0o111 != 0 => (),
+            Ok(_) => problems.push(format!("{} is not executable", bin.display())),
+            Err(e) => problems.push(format!("{} is missing: {}", bin.display(), e)),
+        }
+    }
+    problems
+}
+
+// boots the install, returning the OTP version it reports and any problems loading the applications
+fn check_boot(dist_dir: &Path, required_apps: &[String]) -> (Option<String>, Vec<String>) {
+    let mut apps: Vec<String> = DEFAULT_APPS.iter().map(|a| a.to_string()).collect();
+    apps.extend(required_apps.iter().cloned());
+    apps.sort();
+    apps.dedup();
+
+    // an app without a lib/<app>-<vsn> dir, like a misspelled required app,
+    // isn't booted for but reported as missing
+    let root = erlang_root(dist_dir);
+    let (apps, missing): (Vec<String>, Vec<String>) = apps.into_iter().partition(|app| {
+        glob(
+            root.join("lib")
+                .join(format!("{}-*", app))
+                .to_str()
+                .unwrap(),
+        )
+        .map(|mut paths| paths.any(|p| p.map(|p| p.is_dir()).unwrap_or(false)))
+        .unwrap_or(false)
+    });
+    let mut problems: Vec<String> = missing
+        .iter()
+        .map(|app| {
+            format!(
+                "application {} is not installed in {}",
+                app,
+                root.join("lib").display()
+            )
+        })
+        .collect();
+
+    let output = match boot(dist_dir, &apps) {
+        Ok(output) => output,
+        Err(e) => {
+            problems.push(e);
+            return (None, problems);
+        }
+    };
+
+    let mut vsn = None;
+    for line in output.lines() {
+        let mut words = line.splitn(3, ' ');
+        match (words.next(), words.next(), words.next()) {
+            (Some("version"), Some(v), _) => vsn = Some(v.to_string()),
+            (Some("app"), Some(app), Some(result)) if result != "ok" => {
+                problems.push(format!("application {} failed to load: {}", app, result));
+            }
+            _ => (),
+        }
+    }
+    (vsn, problems)
+}
+
+// the emulator, its helper executables and crypto's NIF are the objects that
+// break when a system library they link against is upgraded or removed
+fn check_shared_libraries(dist_dir: &Path) -> Vec<String> {
+    let ldd = match doctor::find_executable("ldd") {
+        Some(ldd) => ldd,
+        None => {
+            debug!("ldd not found, skipping shared library check");
+            return vec![];
+        }
+    };
+
+    let patterns = [
+        "erts-*/bin/beam*",
+        "erts-*/bin/erl_child_setup",
+        "lib/crypto-*/priv/lib/*.so",
+    ];
+    let root = erlang_root(dist_dir);
+    let objects = patterns.iter().flat_map(|pattern| {
+        glob(root.join(pattern).to_str().unwrap())
+            .map(|paths| paths.filter_map(Result::ok).collect::<Vec<PathBuf>>())
+            .unwrap_or_default()
+    });
+
+    let mut problems = vec![];
+    for object in objects {
+        let output = match Command::new(&ldd).arg(&object).output() {
+            Ok(output) => output,
+            Err(e) => {
+                problems.push(format!("unable to run ldd on {}: {}", object.display(), e));
+                continue;
+            }
+        };
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            if line.contains("not found") {
+                problems.push(format!(
+                    "{}: {}",
+                    object.display(),
+                    line.split_whitespace().collect::<Vec<&str>>().join(" ")
+                ));
+            }
+        }
+    }
+    problems
+}
+
+fn manifest_file(dist_dir: &Path) -> PathBuf {
+    dist_dir.with_file_name("manifest")
+}
+
+/// Records the size and hash of every file in the install to `otps/<id>/manifest`
+/// so `erlup verify --hashes` can later find files that were changed or removed.
+pub fn write_manifest(dist_dir: &Path) {
+    let mut entries = vec![];
+    collect_files(dist_dir, &mut entries);
+    entries.sort();
+
+    let mut manifest = String::new();
+    for file in entries.iter() {
+        if let Ok(contents) = read(file) {
+            let relative = file.strip_prefix(dist_dir).unwrap();
+            manifest.push_str(&format!(
+                "{:016x} {} {}\n",
+                fnv1a(&contents),
+                contents.len(),
+                relative.display()
+            ));
+        }
+    }
+
+    let file = manifest_file(dist_dir);
+    if let Err(e) = write(&file, manifest) {
+        error!("failed writing manifest {}: {}", file.display(), e);
+    }
+}
+
+fn check_manifest(dist_dir: &Path) -> Vec<String> {
+    let file = manifest_file(dist_dir);
+    let manifest = match read_to_string(&file) {
+        Ok(manifest) => manifest,
+        Err(e) => return vec![format!("unable to read {}: {}", file.display(), e)],
+    };
+
+    let mut problems = vec![];
+    for line in manifest.lines() {
+        let mut fields = line.splitn(3, ' ');
+        let (hash, size, relative) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(hash), Some(size), Some(relative)) => (hash, size, relative),
+            _ => continue,
+        };
+        let path = dist_dir.join(relative);
+        match read(&path) {
+            Ok(contents) => {
+                if size != contents.len().to_string()
+                    || hash != format!("{:016x}", fnv1a(&contents))
+                {
+                    problems.push(format!("{} has changed", path.display()));
+                }
+            }
+            Err(_) => problems.push(format!("{} is missing", path.display())),
+        }
+    }
+    problems
+}
+
+// regular files only, symlinks inside the install point at files that are recorded themselves
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    if let Ok(entries) = read_dir(dir) {
+        for entry in entries.filter_map(Result::ok) {
+            match entry.file_type() {
+                Ok(t) if t.is_dir() => collect_files(&entry.path(), files),
+                Ok(t) if t.is_file() => files.push(entry.path()),
+                _ => (),
+            }
+        }
+    }
+}
+
+// FNV-1a is enough to notice damaged files, it isn't meant to detect tampering
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
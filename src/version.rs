@@ -0,0 +1,449 @@
+use glob::glob;
+use std::cmp::Ordering;
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::Path;
+
+/// A parsed OTP release version, from a tag like `OTP-26.2.1`, `OTP-27.0-rc1`
+/// or `OTP_R16B03-1`, or from the contents of an `OTP_VERSION` file.
+#[derive(Debug, Clone)]
+pub struct OtpVersion {
+    // R releases are mapped to [major, 0 for A or 1 for B, minor, patch] so
+    // that R16A < R16B < R16B03 < R16B03-1 < 17.0
+    parts: Vec<u32>,
+    rc: Option<u32>,
+    raw: String,
+}
+
+impl OtpVersion {
+    pub fn parse(s: &str) -> Option<OtpVersion> {
+        let raw = s.trim();
+        // tags may be given with a remote prefix, eg. origin/OTP-26.2
+        let vsn = raw.rsplit('/').next().unwrap_or(raw);
+        let vsn = vsn
+            .strip_prefix("OTP-")
+            .or_else(|| vsn.strip_prefix("OTP_"))
+            .unwrap_or(vsn);
+
+        let (parts, rc) = match vsn.strip_prefix('R') {
+            Some(r) => (parse_r_release(r)?, None),
+            None => parse_release(vsn)?,
+        };
+
+        Some(OtpVersion {
+            parts,
+            rc,
+            raw: raw.to_string(),
+        })
+    }
+
+    /// Parses an Elixir version like `1.17.2`, `v1.17.2` or `1.17.0-rc.0`, so
+    /// Elixir installs can be picked with the same requirements.
+    pub fn parse_elixir(s: &str) -> Option<OtpVersion> {
+        let raw = s.trim();
+        let vsn = raw.strip_prefix('v').unwrap_or(raw);
+        let (numbers, rc) = match vsn.split_once("-rc.") {
+            Some((numbers, rc)) => (numbers, Some(rc.parse::<u32>().ok()?)),
+            None => (vsn, None),
+        };
+        let parts = numbers
+            .split('.')
+            .map(|p| p.parse::<u32>().ok())
+            .collect::<Option<Vec<u32>>>()?;
+        Some(OtpVersion {
+            parts,
+            rc,
+            raw: raw.to_string(),
+        })
+    }
+
+    pub fn major(&self) -> u32 {
+        self.parts[0]
+    }
+
+    pub fn is_rc(&self) -> bool {
+        self.rc.is_some()
+    }
+
+    // trailing zeros don't change the version, 26.2 is the same as 26.2.0
+    fn significant_parts(&self) -> &[u32] {
+        let len = self
+            .parts
+            .iter()
+            .rposition(|p| *p != 0)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        &self.parts[..len]
+    }
+
+    // whether the version starts with `series`, missing parts counting as zeros
+    // like they do when comparing, so 26.2 is in the series 26.2.0
+    fn in_series(&self, series: &[u32]) -> bool {
+        series
+            .iter()
+            .enumerate()
+            .all(|(i, p)| self.parts.get(i).copied().unwrap_or(0) == *p)
+    }
+}
+
+// 26.2.1, 26.2.1.3 or 27.0-rc1
+fn parse_release(vsn: &str) -> Option<(Vec<u32>, Option<u32>)> {
+    let (numbers, rc) = match vsn.split_once("-rc") {
+        Some((numbers, rc)) => (numbers, Some(rc.parse::<u32>().ok()?)),
+        None => (vsn, None),
+    };
+    let parts = numbers
+        .split('.')
+        .map(|p| p.parse::<u32>().ok())
+        .collect::<Option<Vec<u32>>>()?;
+    Some((parts, rc))
+}
+
+// 16B03-1, 16B03, 16B or 16A, without the leading R
+fn parse_r_release(vsn: &str) -> Option<Vec<u32>> {
+    let (vsn, patch) = match vsn.split_once('-') {
+        Some((vsn, patch)) => (vsn, patch.parse::<u32>().ok()?),
+        None => (vsn, 0),
+    };
+    let split = vsn.find(['A', 'B'])?;
+    let major = vsn[..split].parse::<u32>().ok()?;
+    let letter = match &vsn[split..split + 1] {
+        "A" => 0,
+        _ => 1,
+    };
+    let minor = match &vsn[split + 1..] {
+        "" => 0,
+        minor => minor.parse::<u32>().ok()?,
+    };
+    Some(vec![major, letter, minor, patch])
+}
+
+impl Ord for OtpVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.significant_parts()
+            .cmp(other.significant_parts())
+            .then_with(|| match (self.rc, other.rc) {
+                (None, None) => Ordering::Equal,
+                // a release candidate comes before the release itself
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(a), Some(b)) => a.cmp(&b),
+            })
+    }
+}
+
+impl PartialOrd for OtpVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for OtpVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OtpVersion {}
+
+impl fmt::Display for OtpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+/// Parses the tags that are OTP versions and sorts them newest first.
+pub fn sorted_versions(tags: &[String]) -> Vec<OtpVersion> {
+    let mut versions: Vec<OtpVersion> = tags.iter().filter_map(|t| OtpVersion::parse(t)).collect();
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+}
+
+/// A request for the newest version in a series: `latest`, `latest:26`,
+/// `~26.2`, `latest-rc` or `latest-rc:27`. Only `latest-rc` considers
+/// release candidates.
+#[derive(Debug)]
+pub struct LatestSpec {
+    series: Vec<u32>,
+    include_rc: bool,
+    raw: String,
+}
+
+impl LatestSpec {
+    pub fn parse(s: &str) -> Option<LatestSpec> {
+        let (series, include_rc) = if let Some(series) = s.strip_prefix('~') {
+            (series, false)
+        } else if let Some(rest) = s.strip_prefix("latest-rc") {
+            (rest.strip_prefix(':').unwrap_or(rest), true)
+        } else if let Some(rest) = s.strip_prefix("latest") {
+            (rest.strip_prefix(':').unwrap_or(rest), false)
+        } else {
+            return None;
+        };
+
+        let series = match series {
+            "" => vec![],
+            series => series
+                .split('.')
+                .map(|p| p.parse::<u32>().ok())
+                .collect::<Option<Vec<u32>>>()?,
+        };
+        Some(LatestSpec {
+            series,
+            include_rc,
+            raw: s.to_string(),
+        })
+    }
+
+    pub fn matches(&self, v: &OtpVersion) -> bool {
+        (self.include_rc || !v.is_rc()) && v.in_series(&self.series)
+    }
+
+    /// The newest of the tags matching this spec.
+    pub fn select<'a>(&self, versions: &'a [OtpVersion]) -> Option<&'a OtpVersion> {
+        versions.iter().filter(|v| self.matches(v)).max()
+    }
+}
+
+impl fmt::Display for LatestSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.raw)
+    }
+}
+
+#[derive(Debug)]
+enum Op {
+    // a bare version, or one with ~, matches everything in that series
+    Series,
+    Eq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+/// A version requirement like `26`, `~26.2` or `>= 25.3, < 27`, every
+/// comma separated comparison must match.
+#[derive(Debug)]
+pub struct VersionReq {
+    comparators: Vec<(Op, OtpVersion)>,
+}
+
+impl VersionReq {
+    pub fn parse(s: &str) -> Option<VersionReq> {
+        let comparators = s
+            .split(',')
+            .map(|c| {
+                let c = c.trim();
+                let (op, vsn) = [
+                    (">=", Op::Ge),
+                    ("<=", Op::Le),
+                    (">", Op::Gt),
+                    ("<", Op::Lt),
+                    ("=", Op::Eq),
+                    ("~", Op::Series),
+                ]
+                .into_iter()
+                .find_map(|(prefix, op)| c.strip_prefix(prefix).map(|vsn| (op, vsn)))
+                .unwrap_or((Op::Series, c));
+                let vsn = vsn.trim();
+                // only plain version numbers, so ids like OTP-26.2 aren't taken for requirements
+                if !vsn.starts_with(|c: char| c.is_ascii_digit()) {
+                    return None;
+                }
+                OtpVersion::parse(vsn).map(|vsn| (op, vsn))
+            })
+            .collect::<Option<Vec<(Op, OtpVersion)>>>()?;
+        Some(VersionReq { comparators })
+    }
+
+    pub fn matches(&self, v: &OtpVersion) -> bool {
+        // release candidates only match a requirement that names one, so
+        // `< 27` doesn't pick 27.0-rc1
+        if v.is_rc() && !self.comparators.iter().any(|(_, req)| req.is_rc()) {
+            return false;
+        }
+        self.comparators.iter().all(|(op, req)| match op {
+            Op::Series => v.in_series(&req.parts) && v.rc == req.rc,
+            Op::Eq => v == req,
+            Op::Gt => v > req,
+            Op::Ge => v >= req,
+            Op::Lt => v < req,
+            Op::Le => v <= req,
+        })
+    }
+}
+
+/// Reads the full OTP version of an install from its `OTP_VERSION` file, which is
+/// installed under the release directory, eg. lib/erlang/releases/26/OTP_VERSION,
+/// or directly under an install like a distro's /usr/lib/erlang
+pub fn read_otp_version(dist_dir: &Path) -> Option<String> {
+    [
+        "lib/erlang/releases/*/OTP_VERSION",
+        "releases/*/OTP_VERSION",
+    ]
+    .iter()
+    .find_map(|pattern| {
+        glob(dist_dir.join(pattern).to_str().unwrap())
+            .ok()?
+            .filter_map(Result::ok)
+            .last()
+    })
+    .and_then(|f| read_to_string(f).ok())
+    .map(|vsn| vsn.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> OtpVersion {
+        OtpVersion::parse(s).unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        VersionReq::parse(s).unwrap()
+    }
+
+    #[test]
+    fn orders_r_releases_before_numbered_ones() {
+        assert!(v("OTP_R16A") < v("OTP_R16B"));
+        assert!(v("OTP_R16B") < v("OTP_R16B03"));
+        assert!(v("OTP_R16B03") < v("OTP_R16B03-1"));
+        assert!(v("OTP_R16B03-1") < v("OTP-17.0"));
+        assert!(v("OTP_R15B03-1") < v("OTP_R16A"));
+    }
+
+    #[test]
+    fn orders_release_candidates_before_the_release() {
+        assert!(v("OTP-27.0-rc1") < v("OTP-27.0-rc2"));
+        assert!(v("OTP-27.0-rc3") < v("OTP-27.0"));
+        assert!(v("OTP-26.2.5") < v("OTP-27.0-rc1"));
+        assert!(v("27.0-rc1").is_rc());
+    }
+
+    #[test]
+    fn parses_elixir_versions() {
+        let e = |s| OtpVersion::parse_elixir(s).unwrap();
+        assert!(e("1.17.0-rc.0") < e("1.17.0-rc.1"));
+        assert!(e("1.17.0-rc.1") < e("v1.17.0"));
+        assert!(e("1.17.0-rc.0").is_rc());
+        assert!(req("1.17").matches(&e("1.17.2")));
+        assert!(!req("1.17").matches(&e("1.17.0-rc.0")));
+        assert!(OtpVersion::parse_elixir("1.17.0-dev").is_none());
+    }
+
+    #[test]
+    fn orders_numbered_releases() {
+        assert!(v("OTP-26.2.1") < v("OTP-26.2.5"));
+        assert!(v("OTP-26.2.5") < v("OTP-26.2.5.1"));
+        assert!(v("OTP-26.9") < v("OTP-26.10"));
+        assert_eq!(v("OTP-26.2"), v("OTP-26.2.0"));
+    }
+
+    #[test]
+    fn strips_tag_and_remote_prefixes() {
+        assert_eq!(v("OTP-26.2.1"), v("26.2.1"));
+        assert_eq!(v("OTP_R16B03"), v("R16B03"));
+        assert_eq!(v("origin/OTP-26.2"), v("26.2"));
+        assert_eq!(v("refs/tags/OTP-26.2"), v("26.2"));
+        assert_eq!(v("origin/OTP-26.2").to_string(), "origin/OTP-26.2");
+        assert_eq!(v(" 26.2.5\n").major(), 26);
+    }
+
+    #[test]
+    fn rejects_non_versions() {
+        assert!(OtpVersion::parse("maint").is_none());
+        assert!(OtpVersion::parse("OTP-26.x").is_none());
+        assert!(OtpVersion::parse("27.0-rcx").is_none());
+        assert!(OtpVersion::parse("R16C").is_none());
+    }
+
+    #[test]
+    fn sorts_tags_newest_first() {
+        let tags: Vec<String> = [
+            "OTP-26.2",
+            "maint",
+            "OTP-27.0-rc1",
+            "OTP_R16B03",
+            "OTP-27.0",
+        ]
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+        let sorted: Vec<String> = sorted_versions(&tags)
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            sorted,
+            ["OTP-27.0", "OTP-27.0-rc1", "OTP-26.2", "OTP_R16B03"]
+        );
+    }
+
+    #[test]
+    fn parses_requirements() {
+        assert!(VersionReq::parse("26").is_some());
+        assert!(VersionReq::parse("~26.2").is_some());
+        assert!(VersionReq::parse(">= 25.3, < 27").is_some());
+        assert!(VersionReq::parse(">=25.3,<27").is_some());
+        assert!(VersionReq::parse("= 26.2.5").is_some());
+        // ids and names aren't requirements
+        assert!(VersionReq::parse("OTP-26.2").is_none());
+        assert!(VersionReq::parse("default").is_none());
+        assert!(VersionReq::parse(">= 25, maint").is_none());
+        assert!(VersionReq::parse("").is_none());
+    }
+
+    #[test]
+    fn matches_series_ignoring_trailing_zeros() {
+        assert!(req("~26.2.0").matches(&v("26.2")));
+        assert!(req("~26.2.0").matches(&v("26.2.0.1")));
+        assert!(!req("~26.0").matches(&v("26.2")));
+    }
+
+    #[test]
+    fn matches_series_and_comparisons() {
+        assert!(req("26").matches(&v("26.2.5")));
+        assert!(!req("26").matches(&v("27.0")));
+        assert!(req("~26.2").matches(&v("26.2.5")));
+        assert!(!req("~26.2").matches(&v("26.3")));
+        assert!(req(">= 25.3, < 27").matches(&v("26.2.5")));
+        assert!(!req(">= 25.3, < 27").matches(&v("25.2")));
+        assert!(!req(">= 25.3, < 27").matches(&v("27.0")));
+        assert!(req("= 26.2").matches(&v("26.2.0")));
+        assert!(req("<= 26.2").matches(&v("26.2")));
+        assert!(req("> 26.2").matches(&v("26.2.1")));
+    }
+
+    #[test]
+    fn matches_release_candidates_only_when_named() {
+        assert!(!req("< 27").matches(&v("27.0-rc1")));
+        assert!(!req(">= 26").matches(&v("27.0-rc1")));
+        assert!(!req("27").matches(&v("27.0-rc1")));
+        assert!(req(">= 27.0-rc1").matches(&v("27.0-rc2")));
+        assert!(req("~27.0-rc1").matches(&v("27.0-rc1")));
+    }
+
+    #[test]
+    fn selects_latest_in_series() {
+        let versions = sorted_versions(
+            &["OTP-26.2.1", "OTP-26.2.5", "OTP-27.0-rc1", "OTP-25.3"]
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<String>>(),
+        );
+        let select = |s: &str| {
+            LatestSpec::parse(s)
+                .unwrap()
+                .select(&versions)
+                .map(|v| v.to_string())
+        };
+        assert_eq!(select("latest").as_deref(), Some("OTP-26.2.5"));
+        assert_eq!(select("latest-rc").as_deref(), Some("OTP-27.0-rc1"));
+        assert_eq!(select("latest:25").as_deref(), Some("OTP-25.3"));
+        assert_eq!(select("latest:25.3.0").as_deref(), Some("OTP-25.3"));
+        assert_eq!(select("~26.2").as_deref(), Some("OTP-26.2.5"));
+        assert_eq!(select("latest:24"), None);
+    }
+}